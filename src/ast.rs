@@ -1,12 +1,11 @@
-use std::fmt::Display;
-
 pub use super::*;
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 //cell is a basic unit of a script
 //it can be a task, a runtime, a block, a variable etc
 //cells can be nested and can contain other cells
 
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum Cell {
     // assignments are used to define variables
     //e.g `let x = 5;`
@@ -23,11 +22,13 @@ pub enum Cell {
     Block(Block),
     //imports are used to import code from other cells
     //e.g `import "math.moto" as math`
-    #[display("import [:path] as [:alias]")]
     Import(Import),
     ///package is used to define a package
     /// a package has multiple cells    
     Package(Package),
+    ///invocations call a task when the package they are in is executed
+    /// e.g `[:kill_process(process_id: 1234)]`
+    Invocation(Invocation),
 
 }
 
@@ -40,8 +41,9 @@ impl Cell {
             Cell::Task(task) => Some(task.identifer.clone()),
             Cell::Runtime(runtime) => Some(runtime.identifer.clone()),
             Cell::Block(block) => Some(block.identifer.clone()),
-            Cell::Import(import) => Some(import.alias.clone()),
+            Cell::Import(import) => import.alias.clone(),
             Cell::Package(package) => Some(package.identifer.clone()),
+            Cell::Invocation(invocation) => Some(invocation.identifier.clone()),
         }
     }
 
@@ -51,8 +53,9 @@ impl Cell {
             Cell::Task(task) => task.identifer.0.clone(),
             Cell::Runtime(runtime) => runtime.identifer.0.clone(),
             Cell::Block(block) => block.identifer.0.clone(),
-            Cell::Import(import) => import.alias.0.clone(),
+            Cell::Import(import) => import.alias.as_ref().map(|alias| alias.0.clone()).unwrap_or_default(),
             Cell::Package(package) => package.identifer.0.clone(),
+            Cell::Invocation(invocation) => invocation.identifier.0.clone(),
         }
     }
 
@@ -64,6 +67,7 @@ impl Cell {
             Cell::Block(_) => "block".to_string(),
             Cell::Import(_) => "import".to_string(),
             Cell::Package(_) => "package".to_string(),
+            Cell::Invocation(_) => "invocation".to_string(),
         }
     }
 
@@ -73,8 +77,12 @@ impl Cell {
             Cell::Task(task) =>  format!("task {} with runtime {}", task.identifer, task.runtime),
             Cell::Runtime(runtime) =>  format!("runtime {} with runtime {}", runtime.identifer, runtime.runtime),
             Cell::Block(block) =>  format!("block {} with runtime {}", block.identifer, block.runtime),
-            Cell::Import(import) =>  format!("import {} as {}", import.path, import.alias),
+            Cell::Import(import) => match &import.alias {
+                Some(alias) => format!("import {} as {}", import.path, alias),
+                None => format!("import {}", import.path),
+            },
             Cell::Package(package) =>  format!("package {}", package.identifer),
+            Cell::Invocation(invocation) =>  format!("invocation of {}", invocation.identifier),
         }
     }
 
@@ -84,8 +92,56 @@ impl Cell {
             Cell::Task(task) => task.identifier_is(name),
             Cell::Runtime(runtime) => runtime.identifier_is(name),
             Cell::Block(block) => block.identifier_is(name),
-            Cell::Import(import) => import.alias.matches(name),
+            Cell::Import(import) => import.alias.as_ref().is_some_and(|alias| alias.matches(name)),
             Cell::Package(package) => package.identifier_is(name),
+            Cell::Invocation(invocation) => invocation.identifier.matches(name),
+        }
+    }
+
+    pub fn span(&self) -> &Span {
+        match self {
+            Cell::Assignment(assignment) => &assignment.span,
+            Cell::Task(task) => &task.span,
+            Cell::Runtime(runtime) => &runtime.span,
+            Cell::Block(block) => &block.span,
+            Cell::Import(import) => &import.span,
+            Cell::Package(package) => &package.span,
+            Cell::Invocation(invocation) => &invocation.span,
+        }
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Cell::Assignment(assignment) => &mut assignment.span,
+            Cell::Task(task) => &mut task.span,
+            Cell::Runtime(runtime) => &mut runtime.span,
+            Cell::Block(block) => &mut block.span,
+            Cell::Import(import) => &mut import.span,
+            Cell::Package(package) => &mut package.span,
+            Cell::Invocation(invocation) => &mut invocation.span,
+        }
+    }
+
+    /// this cell and its children with their spans cleared, so cells parsed from different places compare equal
+    pub fn without_spans(self) -> Self {
+        match self {
+            Cell::Assignment(assignment) => Cell::Assignment(assignment.without_spans()),
+            Cell::Task(task) => Cell::Task(task.without_spans()),
+            Cell::Runtime(runtime) => Cell::Runtime(runtime.without_spans()),
+            Cell::Block(block) => Cell::Block(block.without_spans()),
+            Cell::Import(import) => Cell::Import(import.without_spans()),
+            Cell::Package(package) => Cell::Package(package.without_spans()),
+            Cell::Invocation(invocation) => Cell::Invocation(invocation.without_spans()),
+        }
+    }
+
+    /// locates the spans of this cell and all of its children in `source`
+    pub fn locate(&mut self, source: &str, file: Option<&str>) {
+        self.span_mut().locate(source, file);
+        match self {
+            Cell::Runtime(runtime) => runtime.children.iter_mut().for_each(|cell| cell.locate(source, file)),
+            Cell::Package(package) => package.children.iter_mut().for_each(|cell| cell.locate(source, file)),
+            _ => {}
         }
     }
 
@@ -110,14 +166,17 @@ impl Cell {
         Cell::Assignment(Assignment {
             identifier: Identifier(identifier.into()),
             value: value.into(),
+            span: Span::default(),
         })
     }
 
     pub fn task(identifer: impl Into<String>, body: impl Into<String>, runtime: impl Into<String>) -> Self {
         Cell::Task(Task {
             identifer: Identifier(identifer.into()),
+            signature: TaskSignature::default(),
             body: body.into(),
             runtime: Identifier(runtime.into()),
+            span: Span::default(),
         })
     }
 
@@ -126,6 +185,7 @@ impl Cell {
             identifer: Identifier(identifer.into()),
             children,
             runtime: Identifier(runtime.into()),
+            span: Span::default(),
         })
     }
 
@@ -134,6 +194,7 @@ impl Cell {
             identifer: Identifier(identifer.into()),
             body: body.into(),
             runtime: Identifier(runtime.into()),
+            span: Span::default(),
         })
     }
 
@@ -142,22 +203,20 @@ impl Cell {
             identifer: Identifier(identifer.into()),
             children,
             runtime: Identifier("moto".to_string()),
+            span: Span::default(),
         })
     }
 
 
 
     pub fn import(path: impl Into<String>, alias: impl Into<String>) -> Self {
-        Cell::Import(Import {
-            path: path.into(),
-            alias: Identifier(alias.into()),
-        })
+        Cell::Import(Import::new(path, alias))
     }
 
 
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 ///packages are used to define a package
 /// a package has multiple cells
 /// e.g `package math { let x = 5; let y = 10; }`
@@ -165,35 +224,46 @@ impl Cell {
 /// or `package math { runtime dart { let version = "3.7.0" }:shell }`
 /// or `package math { block developerCredits { developed by incredimo for xo.rs }:text }`
 /// or `package math { import "math.moto" as math }`
-
 pub struct Package {
+    #[serde(rename = "identifier")]
     pub identifer: Identifier,
     pub children: Vec<Cell>,
     pub runtime: Identifier,
+    pub span: Span,
 }
 
 impl std::fmt::Display for Package {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "package {} {{\n", self.identifer.0)?;
+        writeln!(f, "package {} {{", self.identifer.0)?;
         for child in &self.children {
-            write!(f, "{}\n", child)?;
+            writeln!(f, "{}", child)?;
         }
         write!(f, "}}")
     }
 }
 
-impl Into<Cell> for Package {
-    fn into(self) -> Cell {
-        Cell::Package(self)
+impl From<Package> for Cell {
+    fn from(package: Package) -> Self {
+        Cell::Package(package)
     }
 }
 
 impl Package {
+    /// this package and its children with their spans cleared, see [`Cell::without_spans`]
+    pub fn without_spans(self) -> Self {
+        Self {
+            children: self.children.into_iter().map(Cell::without_spans).collect(),
+            span: Span::default(),
+            ..self
+        }
+    }
+
     pub fn new(identifer: impl Into<String>, children: Vec<Cell>) -> Self {
         Self {
             identifer: Identifier(identifer.into()),
             children,
             runtime: Identifier("moto".to_string()),
+            span: Span::default(),
         }
     }
 
@@ -246,6 +316,16 @@ impl Package {
             .collect()
     }
 
+    pub fn invocations(&self) -> Vec<Invocation> {
+        self.children
+            .iter()
+            .filter_map(|cell| match cell {
+                Cell::Invocation(invocation) => Some(invocation.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn packages(&self) -> Vec<Package> {
         self.children
             .iter()
@@ -273,7 +353,7 @@ impl Package {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 ///assignments are used to define variables
 /// e.g `let x = 5;` or `let x = "hello world";` or `let x = true;`
 /// or `let x = [1,2,3];` or `let x = {a:1, b:2};`
@@ -283,13 +363,20 @@ impl Package {
 pub struct Assignment {
     pub identifier: Identifier,
     pub value: Atom,
+    pub span: Span,
 }
 
 impl Assignment {
+    /// this assignment with its span cleared, see [`Cell::without_spans`]
+    pub fn without_spans(self) -> Self {
+        Self { span: Span::default(), ..self }
+    }
+
     pub fn new(identifier: impl Into<String>, value: impl Into<Atom>) -> Self {
         Self {
             identifier: Identifier(identifier.into()),
             value: value.into(),
+            span: Span::default(),
         }
     }
 
@@ -302,16 +389,19 @@ impl Assignment {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 ///tasks are used to define a sequence of commands
 /// e.g `task greet { echo "hello world" }:shell`
 /// or `task greet { print("hello world"); }:dart`
 /// or `task greet { console.log("hello world"); }:js`
 #[display("task \x1b[33m{identifer}:\x1b[33m{runtime}\x1b[0m")]
 pub struct Task {
+    #[serde(rename = "identifier")]
     pub identifer: Identifier,
+    pub signature: TaskSignature,
     pub body:  String,
     pub runtime: Identifier,
+    pub span: Span,
 }
 
 
@@ -319,11 +409,18 @@ pub struct Task {
 
 
 impl Task {
+    /// this task with its span cleared, see [`Cell::without_spans`]
+    pub fn without_spans(self) -> Self {
+        Self { span: Span::default(), ..self }
+    }
+
     pub fn new(identifer: impl Into<String>, body: impl Into<String>, runtime: impl Into<String>) -> Self {
         Self {
             identifer: Identifier(identifer.into()),
+            signature: TaskSignature::default(),
             body: body.into(),
             runtime: Identifier(runtime.into()),
+            span: Span::default(),
         }
     }
 
@@ -342,9 +439,208 @@ impl Task {
     pub fn get_code(&self) -> String {
         self.body.clone()
     }
+
+    /// the names of the variables the task uses, parameters of the signature first and then
+    /// `[:name]` placeholders of the body in order of appearance. calls like `[:install()]` are not variables,
+    /// neither are names of other packages like `[:rust:version]` or members like `[:env.HOME]`
+    pub fn variables(&self) -> Vec<String> {
+        let mut names = self
+            .signature
+            .parameters()
+            .into_iter()
+            .map(|variable| variable.identifier.0)
+            .collect::<Vec<_>>();
+        for variable in parse_body_variables(&self.body) {
+            let name = variable.name();
+            if !name.contains([':', '.']) && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// the variables the task uses as they are declared, with their defaults and alternatives.
+    /// a name used more than once is listed once, taking the first default and alternatives given for it.
+    /// e.g `command` with `(start | stop)` for `task control [:command=(start | stop)] { echo [:command] }`
+    pub fn declared_variables(&self) -> Vec<Variable> {
+        let mut variables = self.signature.parameters();
+        for variable in parse_body_variables(&self.body) {
+            match variables.iter_mut().find(|known| known.identifier == variable.identifier) {
+                Some(known) => {
+                    if !known.has_default() {
+                        known.value = variable.value;
+                    }
+                    known.constraint = known.constraint.take().or(variable.constraint);
+                }
+                None => variables.push(variable),
+            }
+        }
+        variables
+    }
+
+    /// the header as it was written, e.g `(please)? open (browser | [:path])`
+    pub fn header(&self) -> String {
+        if self.signature.is_empty() {
+            self.name()
+        } else {
+            self.signature.to_string()
+        }
+    }
+
+    /// matches the words of a command line against the task header.
+    /// returns the words captured by parameters when they match, e.g
+    /// `please open firefox.exe` gives `[("path", "firefox.exe")]` for `(please)? open (browser | [:path])`
+    pub fn matches(&self, words: &[String]) -> Option<Vec<(String, String)>> {
+        if self.signature.is_empty() {
+            // anything after the name is ignored, as it always has been for plain tasks
+            let name = self.identifer.0.split_whitespace().collect::<Vec<_>>();
+            let matched = words.len() >= name.len()
+                && name.iter().zip(words).all(|(name, word)| name.eq_ignore_ascii_case(word));
+            return matched.then(Vec::new);
+        }
+        self.signature.matches(words)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+///the header of a task with more than a name
+/// e.g `task go (to)? [:path] { ... }:shell` or `task (please)? open (browser | [:path="chrome.exe"]) { ... }:shell`
+/// tasks declared with a bare name have an empty signature
+#[serde(transparent)]
+pub struct TaskSignature {
+    pub parts: Vec<SignaturePart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum SignaturePart {
+    /// a word that has to be typed as is, e.g `open`
+    Word(String),
+    /// a word captured into a variable, e.g `[:path]` or `[:path="chrome.exe"]`.
+    /// parameters with a default or alternatives can be left out, the last parameter takes the rest of the line
+    Parameter(Variable),
+    /// one of several sequences, e.g `(browser | [:path])`, or `(please)?` when it can be left out
+    Group {
+        alternatives: Vec<Vec<SignaturePart>>,
+        optional: bool,
+    },
+}
+
+impl TaskSignature {
+    pub fn new(parts: Vec<SignaturePart>) -> Self {
+        Self { parts }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// the parameters of the signature, in order of appearance
+    pub fn parameters(&self) -> Vec<Variable> {
+        fn collect(parts: &[SignaturePart], into: &mut Vec<Variable>) {
+            for part in parts {
+                match part {
+                    SignaturePart::Word(_) => {}
+                    SignaturePart::Parameter(variable) => into.push(variable.clone()),
+                    SignaturePart::Group { alternatives, .. } => {
+                        alternatives.iter().for_each(|parts| collect(parts, into))
+                    }
+                }
+            }
+        }
+        let mut parameters = vec![];
+        collect(&self.parts, &mut parameters);
+        parameters
+    }
+
+    /// matches all of `words` against the signature, see [`Task::matches`]
+    pub fn matches(&self, words: &[String]) -> Option<Vec<(String, String)>> {
+        let parts = self.parts.iter().collect::<Vec<_>>();
+        match_parts(&parts, words)
+    }
+}
+
+/// backtracking matcher, parts are tried in order and every way of filling them is
+/// explored until one consumes all of the words
+fn match_parts(parts: &[&SignaturePart], words: &[String]) -> Option<Vec<(String, String)>> {
+    let Some((part, rest)) = parts.split_first() else {
+        return words.is_empty().then(Vec::new);
+    };
+
+    match part {
+        SignaturePart::Word(word) => match words.first() {
+            Some(first) if first.eq_ignore_ascii_case(word) => match_parts(rest, &words[1..]),
+            _ => None,
+        },
+        SignaturePart::Parameter(variable) => {
+            // alternatives left out are picked before the task runs
+            if variable.has_default() || variable.constraint.is_some() {
+                if let Some(bindings) = match_parts(rest, words) {
+                    return Some(bindings);
+                }
+            }
+            // a parameter takes as few words as possible, so only the last one swallows the rest of the line
+            (1..=words.len()).find_map(|taken| {
+                let mut bindings = match_parts(rest, &words[taken..])?;
+                bindings.insert(0, (variable.identifier.0.clone(), words[..taken].join(" ")));
+                Some(bindings)
+            })
+        }
+        SignaturePart::Group { alternatives, optional } => {
+            let taken = alternatives.iter().find_map(|alternative| {
+                let parts = alternative.iter().chain(rest.iter().copied()).collect::<Vec<_>>();
+                match_parts(&parts, words)
+            });
+            match taken {
+                Some(bindings) => Some(bindings),
+                None if *optional => match_parts(rest, words),
+                None => None,
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for TaskSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts = self.parts.iter().map(|part| part.to_string()).collect::<Vec<_>>();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl std::fmt::Display for SignaturePart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignaturePart::Word(word) => write!(f, "{}", word),
+            SignaturePart::Parameter(variable) => {
+                write!(f, "[:{}", variable.identifier.0)?;
+                match &variable.constraint {
+                    Some(VariableConstraint::OneOf(_)) | None => {}
+                    Some(constraint) => write!(f, "::{}", constraint)?,
+                }
+                match (&variable.constraint, &variable.value) {
+                    (Some(constraint @ VariableConstraint::OneOf(_)), _) => write!(f, "={}", constraint)?,
+                    (_, Atom::Null) => {}
+                    (_, Atom::String(value)) => write!(f, "=\"{}\"", value)?,
+                    (_, value) => write!(f, "={}", value)?,
+                }
+                write!(f, "]")
+            }
+            SignaturePart::Group { alternatives, optional } => {
+                let alternatives = alternatives
+                    .iter()
+                    .map(|parts| parts.iter().map(|part| part.to_string()).collect::<Vec<_>>().join(" "))
+                    .collect::<Vec<_>>();
+                write!(f, "({})", alternatives.join(" | "))?;
+                if *optional {
+                    write!(f, "?")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 ///runtimes are used to define a runtime for a specific language
 /// e.g `runtime dart {
 /// let version = "3.7.0"
@@ -358,17 +654,29 @@ impl Task {
 /// }:moto`
 #[display("runtime \x1b[33m{identifer}:\x1b[33m{runtime}\x1b[0m")]
 pub struct Runtime {
+    #[serde(rename = "identifier")]
     pub identifer: Identifier,
     pub children: Vec<Cell>,
     pub runtime: Identifier,
+    pub span: Span,
 }
 
 impl Runtime {
+    /// this runtime and its children with their spans cleared, see [`Cell::without_spans`]
+    pub fn without_spans(self) -> Self {
+        Self {
+            children: self.children.into_iter().map(Cell::without_spans).collect(),
+            span: Span::default(),
+            ..self
+        }
+    }
+
     pub fn new(identifer: impl Into<String>, runtime: impl Into<String> , children: Vec<Cell>) -> Self {
         Self {
             identifer: Identifier(identifer.into()),
             children,
             runtime: Identifier(runtime.into()),
+            span: Span::default(),
         }
     }
 
@@ -385,22 +693,30 @@ impl Runtime {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 ///blocks are used to define reusable blocks of code
 /// e.g `block developerCredits { developed by incredimo for xo.rs }:text`
 #[display("block \x1b[33m{identifer}:\x1b[33m{runtime}\x1b[0m")]
 pub struct Block {
+    #[serde(rename = "identifier")]
     pub identifer: Identifier,
     pub body:  String,
     pub runtime: Identifier,
+    pub span: Span,
 }
 
 impl Block {
+    /// this block with its span cleared, see [`Cell::without_spans`]
+    pub fn without_spans(self) -> Self {
+        Self { span: Span::default(), ..self }
+    }
+
     pub fn new(identifer: impl Into<String>, body: impl Into<String>, runtime: impl Into<String>) -> Self {
         Self {
             identifer: Identifier(identifer.into()),
             body: body.into(),
             runtime: Identifier(runtime.into()),
+            span: Span::default(),
         }
     }
     
@@ -415,31 +731,254 @@ impl Block {
     pub fn runtime(&self) -> String {
         self.runtime.0.clone()
     }
+
+    /// the value `[:name]` expands to once `body` is interpolated. `:json` and `:yaml` blocks
+    /// are parsed into objects and arrays so `[:config.server.port]` can reach into them,
+    /// every other block is text
+    pub fn value(&self, body: &str) -> Result<Atom, String> {
+        match self.runtime().as_str() {
+            "json" => serde_json::from_str::<serde_json::Value>(body)
+                .map(Atom::from)
+                .map_err(|e| e.to_string()),
+            "yaml" | "yml" => serde_yaml::from_str::<serde_json::Value>(&dedent(body))
+                .map(Atom::from)
+                .map_err(|e| e.to_string()),
+            _ => Ok(Atom::String(body.trim().to_string())),
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+/// removes the indentation all lines of a body share, yaml cares about it
+pub fn dedent(body: &str) -> String {
+    let indent = body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    body.lines()
+        .map(|line| line.get(indent..).unwrap_or(line.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 ///imports are used to import code from other cells
-/// e.g `import "math.moto" as math`
-#[display("import [:path] as [:alias]")]
+/// e.g `import "math.moto" as math` or `import math as m;`.
+/// without an alias the cells of the imported file are exposed as if they were written in place
 pub struct Import {
     pub path: String,
-    pub alias: Identifier,
+    pub alias: Option<Identifier>,
+    pub span: Span,
 }
 
 impl Import {
+    /// this import with its span cleared, see [`Cell::without_spans`]
+    pub fn without_spans(self) -> Self {
+        Self { span: Span::default(), ..self }
+    }
+
     pub fn new(path: impl Into<String>, alias: impl Into<String>) -> Self {
         Self {
             path: path.into(),
-            alias: Identifier(alias.into()),
+            alias: Some(Identifier(alias.into())),
+            span: Span::default(),
+        }
+    }
+
+    pub fn exposed(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            alias: None,
+            span: Span::default(),
+        }
+    }
+
+    /// the file to load, relative to the importing file. `import math` loads `math.moto`
+    pub fn file(&self) -> std::path::PathBuf {
+        let path = std::path::PathBuf::from(&self.path);
+        match path.extension() {
+            Some(_) => path,
+            None => path.with_extension("moto"),
         }
     }
 }
 
-#[derive(Debug, Clone, Display)]
+/// imports print the way they are written, e.g `import math as m;` or `import "../tools.moto";`
+impl std::fmt::Display for Import {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bare = !self.path.is_empty()
+            && self.path.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !self.path.starts_with(|c: char| c.is_ascii_digit());
+        match bare {
+            true => write!(f, "import {}", self.path)?,
+            false => write!(f, "import \"{}\"", self.path.replace('\\', "\\\\").replace('"', "\\\""))?,
+        }
+        match &self.alias {
+            Some(alias) => write!(f, " as {};", alias.0),
+            None => write!(f, ";"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+///invocations call a task with arguments when the package they are in is executed
+/// e.g `[:list_processes()]` or `[:kill_process(process_id: 1234)]` or `[:add(a:1, 5)]`
+pub struct Invocation {
+    pub identifier: Identifier,
+    pub arguments: Vec<Argument>,
+    pub span: Span,
+}
+
+impl std::fmt::Display for Invocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let arguments = self.arguments.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        write!(f, "[:{}({})]", self.identifier.0, arguments.join(", "))
+    }
+}
+
+impl Invocation {
+    /// this invocation with its span cleared, see [`Cell::without_spans`]
+    pub fn without_spans(self) -> Self {
+        Self { span: Span::default(), ..self }
+    }
+
+    pub fn new(identifier: impl Into<String>, arguments: Vec<Argument>) -> Self {
+        Self {
+            identifier: Identifier(identifier.into()),
+            arguments,
+            span: Span::default(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.identifier.0.clone()
+    }
+
+    /// binds the arguments to the variables of `task`. named arguments bind first,
+    /// then each positional argument takes the first variable that is still unbound.
+    /// positional arguments left over once every variable is bound are dropped
+    pub fn bind(&self, task: &Task) -> Vec<(String, Atom)> {
+        let mut bound = self
+            .arguments
+            .iter()
+            .filter_map(|argument| Some((argument.name.clone()?.0, argument.value.clone())))
+            .collect::<Vec<_>>();
+        let mut unbound = task
+            .variables()
+            .into_iter()
+            .filter(|name| !bound.iter().any(|(bound, _)| bound == name))
+            .collect::<Vec<_>>()
+            .into_iter();
+        for argument in self.arguments.iter().filter(|argument| argument.name.is_none()) {
+            if let Some(name) = unbound.next() {
+                bound.push((name, argument.value.clone()));
+            }
+        }
+        bound
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+///arguments passed to an invocation, either positional `1234` or named `process_id: 1234`
+pub struct Argument {
+    pub name: Option<Identifier>,
+    pub value: Atom,
+}
+
+impl Argument {
+    pub fn positional(value: impl Into<Atom>) -> Self {
+        Self { name: None, value: value.into() }
+    }
+
+    pub fn named(name: impl Into<String>, value: impl Into<Atom>) -> Self {
+        Self { name: Some(Identifier(name.into())), value: value.into() }
+    }
+}
+
+impl std::fmt::Display for Argument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}: {}", name.0, self.value),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+///spans record where a cell was defined, e.g `im.moto:42`
+/// while parsing, `start` and `end` hold the length of the input left after each end of the cell
+/// since the parsers only ever see the rest of the input. `locate` turns them into byte offsets
+/// once the whole source is known. a span with line 0 has not been located yet
+pub struct Span {
+    pub file: Option<String>,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// span of the text between `before` and `after`, both being the remaining input of a parser.
+    /// trailing whitespace the parser swallowed is not part of the span
+    pub fn between(before: &str, after: &str) -> Self {
+        let consumed = &before[..before.len() - after.len()];
+        Self {
+            file: None,
+            start: before.len(),
+            end: before.len() - consumed.trim_end().len(),
+            line: 0,
+            column: 0,
+        }
+    }
+
+    /// span covering all of `source`
+    pub fn whole(source: &str, file: Option<&str>) -> Self {
+        Self {
+            file: file.map(String::from),
+            start: 0,
+            end: source.len(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    pub fn is_located(&self) -> bool {
+        self.line > 0
+    }
+
+    pub fn locate(&mut self, source: &str, file: Option<&str>) {
+        if !self.is_located() {
+            self.start = source.len().saturating_sub(self.start);
+            self.end = source.len().saturating_sub(self.end);
+            (self.line, self.column) = Span::line_column(source, self.start);
+        }
+        if self.file.is_none() {
+            self.file = file.map(String::from);
+        }
+    }
+
+    /// 1 based line and column (in characters) of the byte `offset` in `source`
+    pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+        let before = &source[..offset.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        (line, before[line_start..].chars().count() + 1)
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file.as_deref().unwrap_or("<input>"), self.line)
+    }
+}
+
+#[derive(Debug, Clone, Display, Serialize, Deserialize)]
 ///identifiers are used to define the name of a variable, task, runtime, block etc
 /// e.g in `let x = 5;` x is the identifier
 /// identifier always printed in bright yellow using ascii escape codes
 #[display("\x1b[33m{}\x1b[0m", "0")]
+#[serde(transparent)]
 pub struct Identifier(pub String);
 
 impl Identifier {
@@ -488,10 +1027,11 @@ impl From<Identifier> for String {
 // }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 ///atoms things that return a value
 ///e.g `5` or `"hello world"` or `true` or `[1,2,3]` or `{a:1, b:2}` or `5 + 5` or `"hello" + "world"`
 /// or `5 + x` or `x + y` or `x + 5` or `x + "hello"` or `"something" + [:x]`
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
 pub enum Atom {
     Number(f64),
     String(String),
@@ -499,6 +1039,7 @@ pub enum Atom {
     Array(Box<Array>),
     Object(Box<Object>),
     BinaryOperation(Box<BinaryOperation>),
+    UnaryOperation(Box<UnaryOperation>),
     Variable(Box<Variable>),
     Function(Box<Function>),
     Null,
@@ -513,7 +1054,8 @@ impl Display for Atom {
             Atom::Array(value) => write!(f, "{}", value),
             Atom::Object(value) => write!(f, "{}", value),
             Atom::BinaryOperation(value) => write!(f, "{}", value),
-            Atom::Variable(value) => write!(f, "{}", value),
+            Atom::UnaryOperation(value) => write!(f, "{}", value),
+            Atom::Variable(value) => write!(f, "[:{}]", value.name()),
             Atom::Function(value) => write!(f, "{}", value),
             Atom::Null => write!(f, "null"),
         }
@@ -553,6 +1095,26 @@ impl Atom {
         }))
     }
 
+    /// the value at `path` inside objects and arrays, e.g `["server", "port"]` or `["hosts", "0"]`
+    pub fn get_path(&self, path: &[&str]) -> Option<Atom> {
+        let Some((first, rest)) = path.split_first() else {
+            return Some(self.clone());
+        };
+        let value = match self {
+            Atom::Object(object) => object.values.iter().find(|(key, _)| key == first).map(|(_, value)| value),
+            Atom::Array(array) => first.parse::<usize>().ok().and_then(|index| array.get(index)),
+            _ => None,
+        }?;
+        value.get_path(rest)
+    }
+
+    pub fn unary_operation(operator: impl Into<String>, operand: impl Into<Atom>) -> Self {
+        Atom::UnaryOperation(Box::new(UnaryOperation {
+            operator: Operator { value: operator.into() },
+            operand: operand.into(),
+        }))
+    }
+
     // pub fn variable(identifier: impl Into<String>, default:  impl Into<Atom>) -> Self {
     //     Atom::Variable(Box::new(Variable {
     //         identifier: Identifier(identifier.into()),
@@ -570,7 +1132,8 @@ impl PartialEq for Atom {
             (Atom::Array(a), Atom::Array(b)) => a == b,
             (Atom::Object(a), Atom::Object(b)) => a == b,
             (Atom::BinaryOperation(a), Atom::BinaryOperation(b)) => a == b,
-            // (Atom::Variable(a), Atom::Variable(b)) => a == b,
+            (Atom::UnaryOperation(a), Atom::UnaryOperation(b)) => a == b,
+            (Atom::Variable(a), Atom::Variable(b)) => a == b,
             (Atom::Function(a), Atom::Function(b)) => a == b,
             (Atom::Null, Atom::Null) => true,
             _ => false,
@@ -603,6 +1166,21 @@ impl From<&str> for Atom {
     }
 }
 
+impl From<serde_json::Value> for Atom {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Atom::Null,
+            serde_json::Value::Bool(value) => Atom::Boolean(value),
+            serde_json::Value::Number(value) => Atom::Number(value.as_f64().unwrap_or_default()),
+            serde_json::Value::String(value) => Atom::String(value),
+            serde_json::Value::Array(values) => Atom::array(values.into_iter().map(Atom::from).collect()),
+            serde_json::Value::Object(values) => {
+                Atom::object(values.into_iter().map(|(key, value)| (key, Atom::from(value))).collect())
+            }
+        }
+    }
+}
+
 impl From<bool> for Atom {
     fn from(value: bool) -> Self {
         Atom::Boolean(value)
@@ -631,11 +1209,11 @@ impl From<Option<Atom>> for Atom {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 ///arrays are used to define a list of values
 /// e.g `[1,2,3]` or `["hello", "world"]` or `[true, false]`
 /// tailing commas are allowed and ignored
-#[display("[values.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(\", \")]")]
+#[display("[{}]", values.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(", "))]
 pub struct Array {
     pub values: Vec<Atom>,
 }
@@ -665,44 +1243,148 @@ impl Array {
     pub fn len(&self) -> usize {
         self.values.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 ///objects are used to define a list of key value pairs
 /// e.g `{a:1, b:2}` or `{name:"incredimo", age:30}`
 /// tailing commas are allowed and ignored
 #[display(
-    "{{values.iter().map(|(k,v)| k.to_string() + \":\" + &v.to_string()).collect::<Vec<String>>().join(\", \")}}"
+    "{{{}}}", values.iter().map(|(k,v)| k.to_string() + ":" + &v.to_string()).collect::<Vec<String>>().join(", ")
 )]
 pub struct Object {
     pub values: Vec<(String, Atom)>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 ///binary operations are used to define operations between two atoms
 /// e.g `5 + 5` or `"hello" + "world"` or `5 + x` or `x + y` or `x + 5` or `x + "hello"` or `"something" + [:x]`
-#[display("[:left] [:operator] [:right]")]
+#[display("{left} {operator} {right}")]
 pub struct BinaryOperation {
     pub left: Atom,
     pub operator: Operator,
     pub right: Atom,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
+///unary operations negate a number or a boolean
+/// e.g `-[:offset]` or `![:verbose]`
+#[display("{operator}{operand}")]
+pub struct UnaryOperation {
+    pub operator: Operator,
+    pub operand: Atom,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 ///variables are used to reference a value
 /// e.g `[:name]` or `[:x=5]` or `[:x="hello"]` or `[:x=true]` or `[:x=[1,2,3]]` or `[:x={a:1, b:2}]`
-/// default value is optional and can be omitted
+/// default value is optional and can be omitted, `[:command=(start | stop)]` takes one of the alternatives instead
 #[display("var {identifier}")]
 pub struct Variable {
     pub identifier: Identifier,
     pub value: Atom,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<VariableConstraint>,
 }
 
-impl Into<Cell> for Variable {
-    fn into(self) -> Cell {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+///what a variable can be set to, given in task headers like `[:port::number=8080]` or `[:command=(start | stop)]`
+pub enum VariableConstraint {
+    ///one of the alternatives, e.g `start` or `stop` for `[:command=(start | stop)]`
+    OneOf(Vec<Atom>),
+    ///`[:port::number]`
+    Number,
+    ///`[:verbose::bool]`, `true`, `yes`, `y`, `on` and `1` or their opposites
+    Bool,
+    ///`[:path::file]`, the path of a file that exists
+    File,
+    ///`[:tag::/v\d+/]`, text the whole of which matches the regular expression
+    Matches(String),
+}
+
+impl VariableConstraint {
+    /// `value` as the variant of [`Atom`] the constraint asks for, e.g the number `8080` for the text `8080`
+    /// and `true` for `yes`. the error says what was expected instead.
+    /// values given on the command line are text, so alternatives are compared as text
+    pub fn coerce(&self, value: &Atom) -> Result<Atom, String> {
+        let text = value.to_string();
+        let expected = |what: &str| Err(format!("expected {}", what));
+        match self {
+            VariableConstraint::OneOf(choices) => match choices.iter().find(|choice| choice.to_string() == text) {
+                Some(choice) => Ok(choice.clone()),
+                None => Err(format!("it can be {}", self.choices().join(" or "))),
+            },
+            VariableConstraint::Number => match (value, text.trim().parse::<f64>()) {
+                (Atom::Number(_), _) => Ok(value.clone()),
+                (_, Ok(number)) => Ok(Atom::Number(number)),
+                _ => expected("a number"),
+            },
+            VariableConstraint::Bool => match text.trim().to_lowercase().as_str() {
+                "true" | "yes" | "y" | "on" | "1" => Ok(Atom::Boolean(true)),
+                "false" | "no" | "n" | "off" | "0" => Ok(Atom::Boolean(false)),
+                _ => expected("yes or no"),
+            },
+            VariableConstraint::File => match std::path::Path::new(&text).is_file() {
+                true => Ok(Atom::String(text)),
+                false => expected("an existing file"),
+            },
+            VariableConstraint::Matches(pattern) => match regex::Regex::new(&format!("^(?:{})$", pattern)) {
+                Ok(regex) if regex.is_match(&text) => Ok(Atom::String(text)),
+                _ => expected(&format!("text matching /{}/", pattern)),
+            },
+        }
+    }
+
+    /// whether `value` is allowed, see [`VariableConstraint::coerce`]
+    pub fn allows(&self, value: &Atom) -> bool {
+        self.coerce(value).is_ok()
+    }
+
+    /// the values that are allowed, empty when there is no list of them
+    pub fn choices(&self) -> Vec<String> {
+        match self {
+            VariableConstraint::OneOf(choices) => choices.iter().map(Atom::to_string).collect(),
+            VariableConstraint::Bool => vec!["yes".to_string(), "no".to_string()],
+            _ => vec![],
+        }
+    }
+}
+
+/// `(start | stop)` for alternatives, the type like `number` or `/v\d+/` otherwise
+impl std::fmt::Display for VariableConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VariableConstraint::OneOf(choices) => {
+                let choices = choices
+                    .iter()
+                    .map(|choice| match choice {
+                        Atom::String(text) if text.is_empty() || text.contains(|c: char| c.is_whitespace() || "|()[]\"'".contains(c)) => {
+                            format!("{:?}", text)
+                        }
+                        choice => choice.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "({})", choices.join(" | "))
+            }
+            VariableConstraint::Number => write!(f, "number"),
+            VariableConstraint::Bool => write!(f, "bool"),
+            VariableConstraint::File => write!(f, "file"),
+            VariableConstraint::Matches(pattern) => write!(f, "/{}/", pattern),
+        }
+    }
+}
+
+impl From<Variable> for Cell {
+    fn from(variable: Variable) -> Self {
         Cell::Assignment(Assignment {
-            identifier: self.identifier,
-            value: self.value,
+            identifier: variable.identifier,
+            value: variable.value,
+            span: Span::default(),
         })
     }
 }
@@ -712,6 +1394,7 @@ impl Variable {
         Self {
             identifier: Identifier(identifier.into()),
             value: value.into(),
+            constraint: None,
         }
     }
 
@@ -719,9 +1402,28 @@ impl Variable {
         Self {
             identifier: Identifier(identifier.into()),
             value: default,
+            constraint: None,
         }
     }
 
+    /// a variable that takes one of `choices`, e.g `[:command=(start | stop)]`
+    pub fn one_of(identifier: impl Into<String>, choices: Vec<Atom>) -> Self {
+        Self { constraint: Some(VariableConstraint::OneOf(choices)), ..Variable::new(identifier, Atom::Null) }
+    }
+
+    /// `value` as the constraint of the variable asks for it, see [`VariableConstraint::coerce`].
+    /// fails when it is not allowed, e.g `restart` for `[:command=(start | stop)]` or `abc` for `[:port::number]`
+    pub fn coerce(&self, value: &Atom) -> Result<Atom, MotoError> {
+        let Some(constraint) = &self.constraint else {
+            return Ok(value.clone());
+        };
+        constraint.coerce(value).map_err(|reason| MotoError::InvalidValue {
+            name: self.name(),
+            value: value.to_string(),
+            reason,
+        })
+    }
+
     pub fn has_default(&self) -> bool {
         self.value != Atom::Null
     }
@@ -755,7 +1457,7 @@ impl Variable {
         self.identifier = Identifier(identifier.into());
     }
     
-    pub(crate) fn get_value_or(&self, default_value: Atom) ->  Atom {
+    pub fn get_value_or(&self, default_value: Atom) ->  Atom {
         if self.value == Atom::Null {
             default_value
         } else {
@@ -766,12 +1468,10 @@ impl Variable {
 
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 ///functions are used to reference a function
 /// e.g `[:print("hello world")]` or `[:console.log("hello world")]`
-#[display(
-    "[:identifier]([:arguments.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(\", \")])"
-)]
+#[display("[:{}({})]", identifier.0, arguments.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(", "))]
 pub struct Function {
     pub identifier: Identifier,
     pub arguments: Vec<Atom>,
@@ -802,10 +1502,10 @@ impl Function {
     }
 }
 
-#[derive(Debug, Clone, Display)]
+#[derive(Debug, Clone, Display, Serialize, Deserialize)]
 ///operators are used to define operations between two atoms
 /// e.g `+` or `-` or `*` or `/` or `==` or `!=` or `>` or `<` or `>=` or `<=` or `&&` or `||`
-
+#[serde(transparent)]
 pub struct Operator {
     pub value: String,
 }
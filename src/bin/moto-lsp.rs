@@ -0,0 +1,7 @@
+//! the moto language server. editors start it and talk to it over stdin and stdout
+fn main() {
+    if let Err(error) = moto::lsp::run() {
+        eprintln!("moto-lsp: {}", error);
+        std::process::exit(error.exit_code());
+    }
+}
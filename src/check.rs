@@ -0,0 +1,276 @@
+use super::*;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq)]
+///a problem found in a script without running it, e.g `im.moto:12: task build uses runtime dart, which is not defined`
+pub struct Diagnostic {
+    ///where the problem is, e.g `im.moto:12`
+    pub location: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(location: impl ToString, message: impl Into<String>) -> Self {
+        Self { location: location.to_string(), message: message.into() }
+    }
+
+    /// a file that could not be loaded, e.g a parse error, an import of a file that does not exist or an import cycle
+    pub fn from_error(file: &str, error: &MotoError) -> Self {
+        match error {
+            MotoError::Parse(error) => Diagnostic::new(error.location(), error.message()),
+            MotoError::Import { path, imported_at, source } => {
+                Diagnostic::new(imported_at, format!("could not import {}: {}", path, source))
+            }
+            error => Diagnostic::new(file, error.to_string()),
+        }
+    }
+
+    /// the file and the line of the location, `("im.moto", 12)` for `im.moto:12`. a location without a line is line 0
+    pub fn position(&self) -> (&str, usize) {
+        match self.location.rsplit_once(':').map(|(file, line)| (file, line.parse())) {
+            Some((file, Ok(line))) => (file, line),
+            _ => (&self.location, 0),
+        }
+    }
+}
+
+/// sorts `diagnostics` by file and line, those of the same line keep their order
+pub fn sort_diagnostics(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by(|a, b| a.position().cmp(&b.position()));
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// a cell at any depth with the namespace it is defined in and the `runtime` cell it is part of, if any
+struct Located<'a> {
+    namespace: Vec<String>,
+    cell: &'a Cell,
+    runtime: Option<&'a Runtime>,
+}
+
+fn locate<'a>(cells: &'a [Cell], namespace: &mut Vec<String>, runtime: Option<&'a Runtime>, into: &mut Vec<Located<'a>>) {
+    for cell in cells {
+        into.push(Located { namespace: namespace.clone(), cell, runtime });
+        match cell {
+            Cell::Runtime(runtime) => locate(&runtime.children, namespace, Some(runtime), into),
+            Cell::Package(package) => {
+                namespace.push(package.name());
+                locate(&package.children, namespace, None, into);
+                namespace.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Ctx {
+    /// checks the cells of this context without running anything. it reports task tails naming runtimes
+    /// that don't exist, runtimes without a `run` task, calls of tasks that don't exist, tasks defined twice
+    /// and `[:variables]` without a default that no `let`, block, signature, call or the environment gives a value.
+    /// the diagnostics come by file and line
+    pub async fn check(&self) -> Result<Vec<Diagnostic>, MotoError> {
+        let cells = self.get_children().await;
+        let mut located = vec![];
+        locate(&cells, &mut vec![], None, &mut located);
+        let mut diagnostics = vec![];
+
+        // the variables every task gets from the calls to it, by qualified name
+        let mut bound = HashMap::<String, HashSet<String>>::new();
+        for Located { namespace, cell, .. } in &located {
+            let scope = self.within(namespace.clone());
+            for invocation in calls(cell) {
+                match scope.get_task(&invocation.identifier.0).await {
+                    Ok(task) => bound
+                        .entry(task.qualify(&task.cell.name()))
+                        .or_default()
+                        .extend(invocation.bind(&task.cell).into_iter().map(|(name, _)| name)),
+                    Err(error) => diagnostics.push(Diagnostic::new(cell.span(), error.to_string())),
+                }
+            }
+        }
+
+        for Located { namespace, cell, runtime } in &located {
+            let scope = self.within(namespace.clone());
+            let mut given = HashSet::new();
+            let variables = match cell {
+                Cell::Task(task) => {
+                    match scope.get_executor(&task.runtime.0).await {
+                        Ok(_) => {}
+                        Err(MotoError::RuntimeNotFound { .. }) => diagnostics.push(Diagnostic::new(
+                            &task.span,
+                            format!("task {} uses runtime {}, which is not defined", task.name(), task.runtime.0),
+                        )),
+                        Err(error) => diagnostics.push(Diagnostic::new(&task.span, error.to_string())),
+                    }
+                    given.extend(task.signature.parameters().into_iter().map(|variable| variable.identifier.0));
+                    given.extend(bound.get(&qualify(namespace, &task.name())).into_iter().flatten().cloned());
+                    if let Some(runtime) = runtime {
+                        given.insert("block".to_string());
+                        given.extend(runtime.assignments().into_iter().map(|assignment| assignment.name()));
+                    }
+                    body_variables(&task.body)
+                }
+                Cell::Block(block) => body_variables(&block.body),
+                Cell::Assignment(assignment) => {
+                    if let Some(runtime) = runtime {
+                        given.extend(runtime.assignments().into_iter().map(|assignment| assignment.name()));
+                    }
+                    let mut variables = vec![];
+                    atom_references(&assignment.value, &mut variables, &mut vec![]);
+                    variables
+                }
+                Cell::Runtime(runtime) => {
+                    if !runtime.children.iter().any(|cell| matches!(cell, Cell::Task(task) if task.identifier_is("run"))) {
+                        diagnostics.push(Diagnostic::new(
+                            &runtime.span,
+                            format!("runtime {} has no run task, the tasks using it have nothing to run them", runtime.name()),
+                        ));
+                    }
+                    vec![]
+                }
+                _ => vec![],
+            };
+            // `[:env.HOME]` depends on where the script runs, it can't be checked
+            let variables = variables.into_iter().filter(|variable| !given.contains(variable) && !variable.starts_with("env."));
+            for variable in variables {
+                if scope.given(&variable).await.is_some() {
+                    continue;
+                }
+                match scope.defines_value(&variable).await {
+                    Ok(true) => {}
+                    Ok(false) => diagnostics.push(Diagnostic::new(
+                        cell.span(),
+                        format!("{} uses [:{}], which has no default and is not set anywhere", cell.name(), variable),
+                    )),
+                    Err(error) => diagnostics.push(Diagnostic::new(cell.span(), error.to_string())),
+                }
+            }
+        }
+
+        let mut tasks = vec![];
+        namespaced_tasks(&cells, &mut vec![], &mut tasks);
+        for (i, task) in tasks.iter().enumerate() {
+            let same_namespace = |other: &&Resolved<Task>| other.namespace == task.namespace;
+            let other_file = |other: &&Resolved<Task>| other.cell.span.file != task.cell.span.file;
+            let Some(first) = tasks[..i].iter().find(|other| {
+                other.cell.identifier_is(task.cell.name())
+                    && other.cell.signature == task.cell.signature
+                    && (same_namespace(other) || other_file(other))
+            }) else {
+                continue;
+            };
+            let message = match same_namespace(&first) {
+                true => format!("task {} is already defined at {}, this one is never used", task.cell.name(), first.cell.span),
+                false => format!("task {} is also defined at {}, `moto {}` can't tell them apart", task.cell.name(), first.cell.span, task.cell.name()),
+            };
+            diagnostics.push(Diagnostic::new(&task.cell.span, message));
+        }
+        sort_diagnostics(&mut diagnostics);
+        Ok(diagnostics)
+    }
+}
+
+/// the task calls made by a cell: invocations, `[:call()]` in bodies and calls in `let` values
+fn calls(cell: &Cell) -> Vec<Invocation> {
+    match cell {
+        Cell::Invocation(invocation) => vec![invocation.clone()],
+        Cell::Task(Task { body, .. }) | Cell::Block(Block { body, .. }) => body
+            .match_indices("[:")
+            .filter_map(|(start, _)| parse_invocation(&body[start..]).ok().map(|(_, invocation)| invocation))
+            .collect(),
+        Cell::Assignment(assignment) => {
+            let mut calls = vec![];
+            atom_references(&assignment.value, &mut vec![], &mut calls);
+            calls
+        }
+        _ => vec![],
+    }
+}
+
+/// the names of the `[:variables]` without a default in a body, e.g `name` for `echo hello [:name]`.
+/// alternatives like `[:name=(mom | dad)]` are picked when the task runs
+fn body_variables(body: &str) -> Vec<String> {
+    let mut names = vec![];
+    for variable in parse_body_variables(body) {
+        let picked = matches!(variable.constraint, Some(VariableConstraint::OneOf(_)));
+        if !variable.has_default() && !picked && !names.contains(&variable.name()) {
+            names.push(variable.name());
+        }
+    }
+    names
+}
+
+/// the variables without a default and the calls in an expression
+fn atom_references(atom: &Atom, variables: &mut Vec<String>, calls: &mut Vec<Invocation>) {
+    match atom {
+        Atom::Variable(variable) if !variable.has_default() => variables.push(variable.name()),
+        Atom::Function(function) => {
+            let arguments = function.arguments.iter().cloned().map(Argument::positional).collect();
+            calls.push(Invocation::new(function.name().0, arguments));
+            function.arguments.iter().for_each(|argument| atom_references(argument, variables, calls));
+        }
+        Atom::Array(array) => array.values.iter().for_each(|value| atom_references(value, variables, calls)),
+        Atom::Object(object) => object.values.iter().for_each(|(_, value)| atom_references(value, variables, calls)),
+        Atom::UnaryOperation(operation) => atom_references(&operation.operand, variables, calls),
+        Atom::BinaryOperation(operation) => {
+            atom_references(&operation.left, variables, calls);
+            atom_references(&operation.right, variables, calls);
+        }
+        _ => {}
+    }
+}
+
+#[tokio::test]
+async fn test_check() {
+    let input = "task build { echo [:target] [:name=x] }:dart\ntask build { echo }:shell\ntask deploy { echo [:env] }:shell\nruntime dart { task compile { echo [:block] }:shell }:moto\n[:deploy(env: prod)]\n";
+    let ctx = Ctx::from_source(input).await;
+    let messages = ctx.check().await.unwrap().into_iter().map(|diagnostic| diagnostic.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "im.moto:1: build uses [:target], which has no default and is not set anywhere",
+            "im.moto:2: task build is already defined at im.moto:1, this one is never used",
+            "im.moto:4: runtime dart has no run task, the tasks using it have nothing to run them",
+        ]
+    );
+}
+
+#[test]
+fn test_check_imports() {
+    let dir = std::env::temp_dir().join(format!("moto-check-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.moto"), "task a { echo }:shell\nimport missing;\n").unwrap();
+    std::fs::write(dir.join("b.moto"), "import c;\n").unwrap();
+    std::fs::write(dir.join("c.moto"), "import b;\n").unwrap();
+    let mut loader = Loader::default();
+    let diagnostic = |file: &str, loader: &mut Loader| {
+        let path = dir.join(file);
+        let error = loader.load_package(&path).unwrap_err();
+        Diagnostic::from_error(&path.display().to_string(), &error)
+    };
+
+    let missing = diagnostic("a.moto", &mut loader);
+    assert!(missing.location.ends_with("a.moto:2"), "{}", missing.location);
+    assert!(missing.message.starts_with("could not import missing: "), "{}", missing.message);
+    let cycle = diagnostic("b.moto", &mut loader);
+    assert!(cycle.message.starts_with("import cycle: "), "{}", cycle.message);
+    assert!(cycle.message.ends_with("b.moto"), "{}", cycle.message);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_sort_diagnostics() {
+    let mut diagnostics = vec![
+        Diagnostic::new("b.moto:1", "b"),
+        Diagnostic::new("a.moto:10", "ten"),
+        Diagnostic::new("a.moto:9", "nine"),
+        Diagnostic::new("a.moto", "a"),
+    ];
+    sort_diagnostics(&mut diagnostics);
+    let messages = diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>();
+    assert_eq!(messages, ["a", "nine", "ten", "b"]);
+}
@@ -0,0 +1,179 @@
+use super::*;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// runs the command built into moto that `args` name, e.g `moto fmt --check`.
+/// `None` when the first word is no command, it is then taken as a task.
+/// commands win over tasks of the same name, `moto file:fmt` still reaches a `task fmt`
+pub async fn run_command(args: &[String]) -> Option<Result<(), MotoError>> {
+    let (command, args) = args.split_first()?;
+    match command.as_str() {
+        "fmt" => Some(fmt(args)),
+        "check" => Some(check(args).await),
+        "dump" => Some(dump(args)),
+        "vars" => Some(vars(args).await),
+        _ => None,
+    }
+}
+
+/// `moto fmt [--check] [files]` writes the files, all `.moto` files of the current directory by default,
+/// in the canonical layout. with `--check` nothing is written and unformatted files are an error.
+/// a file that can't be formatted is reported and the others are formatted anyway
+pub fn fmt(args: &[String]) -> Result<(), MotoError> {
+    let check = args.iter().any(|arg| arg == "--check");
+    let mut unformatted = vec![];
+    let mut failed = vec![];
+    for path in files(args)? {
+        let file = path.display().to_string();
+        let formatted = match formatted(&path, &file) {
+            Ok(Some(formatted)) => formatted,
+            Ok(None) => continue,
+            Err(error) => {
+                match &error {
+                    MotoError::Parse(error) => show_parse_error(error),
+                    error => show_error(&error.to_string()),
+                }
+                failed.push(file);
+                continue;
+            }
+        };
+        if check {
+            showln!(yellow_bold, "not formatted ", gray_dim, &file);
+            unformatted.push(file);
+        } else if let Err(error) = fs::write(&path, formatted) {
+            show_error(&format!("{}: {}", file, error));
+            failed.push(file);
+        } else {
+            showln!(green_bold, "formatted ", gray_dim, &file);
+        }
+    }
+    match (failed.is_empty(), unformatted.is_empty()) {
+        (false, _) => Err(MotoError::FormatFailed { files: failed }),
+        (true, false) => Err(MotoError::Unformatted { files: unformatted }),
+        (true, true) => Ok(()),
+    }
+}
+
+/// the file at `path` in the canonical layout, `None` when it already is
+fn formatted(path: &Path, file: &str) -> Result<Option<String>, MotoError> {
+    let source = fs::read_to_string(path)?;
+    let formatted = format_source(file, &source)?;
+    Ok((formatted != source).then_some(formatted))
+}
+
+/// `moto check [files]` loads the files, all `.moto` files of the current directory by default, and shows
+/// what [`Ctx::check`] finds along with the files that could not be loaded. nothing is run
+pub async fn check(args: &[String]) -> Result<(), MotoError> {
+    let ctx = Ctx::empty();
+    ctx.load_environment(&std::env::current_dir()?).await?;
+    let mut loader = Loader::default();
+    let mut diagnostics = vec![];
+    let mut packages = vec![];
+    for path in files(args)? {
+        match loader.load_package(&path) {
+            Ok(package) => packages.push((path, package)),
+            Err(error) => diagnostics.push(Diagnostic::from_error(&path.display().to_string(), &error)),
+        }
+    }
+    for (path, package) in packages {
+        if !loader.is_imported(&path) {
+            ctx.push_cell(package).await;
+        }
+    }
+    diagnostics.extend(ctx.check().await?);
+    sort_diagnostics(&mut diagnostics);
+
+    for diagnostic in &diagnostics {
+        showln!(yellow_bold, &diagnostic.location, gray_dim, ": ", white_bold, &diagnostic.message);
+    }
+    match diagnostics.len() {
+        0 => {
+            showln!(green_bold, "no problems found");
+            Ok(())
+        }
+        problems => Err(MotoError::CheckFailed { problems }),
+    }
+}
+
+/// `moto dump --json [files]` prints the packages moto finds, the files of the current directory by default,
+/// with all of their cells and where they are defined as json, for tools that need moto's definitions.
+/// a file that can't be loaded is reported on stderr and the others are printed anyway
+pub fn dump(args: &[String]) -> Result<(), MotoError> {
+    if !args.iter().any(|arg| arg == "--json") {
+        return Err(MotoError::Usage { usage: "moto dump --json [files]".to_string() });
+    }
+    let mut loader = Loader::default();
+    let mut packages = vec![];
+    let mut failed = vec![];
+    for path in files(args)? {
+        match loader.load_package(&path) {
+            Ok(package) => packages.push((package, path)),
+            Err(error) => {
+                let file = path.display().to_string();
+                // stdout is the json, parse errors already name their file
+                match &error {
+                    MotoError::Parse(error) => writeln!(std::io::stderr(), "{}", error)?,
+                    error => writeln!(std::io::stderr(), "{}: {}", file, error)?,
+                }
+                failed.push(file);
+            }
+        }
+    }
+    let cells = packages
+        .into_iter()
+        .filter(|(_, path)| !loader.is_imported(path))
+        .map(|(package, _)| Cell::Package(package))
+        .collect::<Vec<_>>();
+    #[derive(Serialize)]
+    struct Dump {
+        version: &'static str,
+        cells: Vec<Cell>,
+    }
+    let dump = Dump { version: env!("CARGO_PKG_VERSION"), cells };
+    let json = serde_json::to_string_pretty(&dump).map_err(|e| MotoError::Io(e.into()))?;
+    writeln!(std::io::stdout(), "{}", json)?;
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(MotoError::LoadFailed { files: failed }),
+    }
+}
+
+/// `moto vars [[:name=value]..]` shows every variable of the scripts of the current directory and the ones
+/// given by the environment, with the value it has and where that comes from. variables given as arguments
+/// are taken as they would be by `moto <task>`
+pub async fn vars(args: &[String]) -> Result<(), MotoError> {
+    let ctx = Ctx::empty();
+    scan(&ctx).await?;
+    let (_, variables) = parse_args(args);
+    for variable in variables {
+        ctx.variables.lock().await.insert(variable.name().trim().to_lowercase(), variable.get_value());
+    }
+    let settings = ctx.settings().await;
+    let width = settings.iter().map(|(name, _)| name.len()).max().unwrap_or_default();
+    for (name, setting) in settings {
+        let value = setting.value.to_string();
+        showln!(cyan_bold, format!("{:width$}", name), gray_dim, " = ", white, value, gray_dim, "  ", setting.source.to_string());
+    }
+    Ok(())
+}
+
+/// the files named in `args`, all `.moto` files of the current directory when there are none
+fn files(args: &[String]) -> Result<Vec<PathBuf>, MotoError> {
+    let files = args.iter().filter(|arg| !arg.starts_with("--")).map(PathBuf::from).collect::<Vec<_>>();
+    match files.is_empty() {
+        true => moto_files(),
+        false => Ok(files),
+    }
+}
+
+/// the `.moto` files of the current directory
+fn moto_files() -> Result<Vec<PathBuf>, MotoError> {
+    let current_dir = std::env::current_dir()?;
+    let pattern = format!("{}/*.moto", glob::Pattern::escape(&current_dir.to_string_lossy()));
+    let entries = glob::glob(&pattern).map_err(|e| MotoError::Io(std::io::Error::other(e)))?;
+    Ok(entries
+        .filter_map(Result::ok)
+        .map(|path| path.strip_prefix(&current_dir).map(PathBuf::from).unwrap_or(path))
+        .collect())
+}
@@ -0,0 +1,310 @@
+use super::*;
+
+/// one level of indentation in formatted files
+const INDENT: &str = "    ";
+
+#[derive(Debug, Clone, PartialEq)]
+///a moto file as it was written, comments and whitespace included.
+/// printing it gives back the source byte for byte, [`SyntaxTree::format`] gives it in the canonical layout
+pub struct SyntaxTree {
+    pub nodes: Vec<SyntaxNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+///a piece of a moto file, either the trivia between cells or a cell
+pub enum SyntaxNode {
+    Whitespace(String),
+    ///`// up to the end of the line` or `/* anything */`
+    Comment(String),
+    ///text between cells the parser did not read, kept as it is
+    Unparsed(String),
+    Cell(CellNode),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+///a cell along with the text it was written as
+pub struct CellNode {
+    pub cell: Cell,
+    pub syntax: CellSyntax,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CellSyntax {
+    ///cells without children keep their whole text, e.g `let x = 5;`
+    Leaf(String),
+    ///packages and runtimes: the header up to `{`, the children with the trivia between them and `}:tail`
+    Container { open: String, children: Vec<SyntaxNode>, close: String },
+}
+
+impl SyntaxTree {
+    /// parses `source` read from `file`, keeping everything the parser skips
+    pub fn parse(file: &str, source: &str) -> Result<SyntaxTree, ParseError> {
+        let cells = parse_file(file, source)?;
+        Ok(SyntaxTree { nodes: nodes(source, 0, source.len(), cells) })
+    }
+
+    /// the file in the canonical layout: four spaces per level, at most one blank line between cells,
+    /// bodies re-indented under their header, `}:tail` on its own line and comments kept where they were
+    pub fn format(&self) -> String {
+        let mut out = String::new();
+        format_nodes(&self.nodes, 0, false, &mut out);
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// the nodes of `source[start..end]`, which holds `cells` and the trivia around them
+fn nodes(source: &str, start: usize, end: usize, cells: Vec<Cell>) -> Vec<SyntaxNode> {
+    let mut nodes = vec![];
+    let mut cursor = start;
+    for cell in cells {
+        let span = cell.span().clone();
+        trivia(&source[cursor..span.start], &mut nodes);
+        nodes.push(SyntaxNode::Cell(cell_node(source, cell)));
+        cursor = span.end;
+    }
+    trivia(&source[cursor..end], &mut nodes);
+    nodes
+}
+
+fn cell_node(source: &str, cell: Cell) -> CellNode {
+    let span = cell.span().clone();
+    let text = &source[span.start..span.end];
+    let children = match &cell {
+        Cell::Runtime(runtime) => Some(runtime.children.clone()),
+        Cell::Package(package) => Some(package.children.clone()),
+        _ => None,
+    };
+    let syntax = match (children, text.find('{'), text.rfind("}:")) {
+        (Some(children), Some(open), Some(close)) => CellSyntax::Container {
+            open: text[..=open].to_string(),
+            children: nodes(source, span.start + open + 1, span.start + close, children),
+            close: text[close..].to_string(),
+        },
+        _ => CellSyntax::Leaf(text.to_string()),
+    };
+    CellNode { cell, syntax }
+}
+
+/// splits the text between two cells into whitespace and comments
+fn trivia(mut text: &str, nodes: &mut Vec<SyntaxNode>) {
+    while !text.is_empty() {
+        let (node, length) = if text.starts_with("//") {
+            (SyntaxNode::Comment as fn(String) -> SyntaxNode, text.find('\n').unwrap_or(text.len()))
+        } else if text.starts_with("/*") {
+            (SyntaxNode::Comment as _, text.find("*/").map(|end| end + 2).unwrap_or(text.len()))
+        } else if text.starts_with(char::is_whitespace) {
+            (SyntaxNode::Whitespace as _, text.find(|c: char| !c.is_whitespace()).unwrap_or(text.len()))
+        } else {
+            // the parser skips nothing else, anything that shows up anyway is kept as it is
+            (SyntaxNode::Unparsed as _, text.find(char::is_whitespace).unwrap_or(text.len()))
+        };
+        nodes.push(node(text[..length].to_string()));
+        text = &text[length..];
+    }
+}
+
+impl std::fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.nodes.iter().try_for_each(|node| write!(f, "{}", node))
+    }
+}
+
+impl std::fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyntaxNode::Whitespace(text) | SyntaxNode::Comment(text) | SyntaxNode::Unparsed(text) => write!(f, "{}", text),
+            SyntaxNode::Cell(cell) => write!(f, "{}", cell),
+        }
+    }
+}
+
+impl std::fmt::Display for CellNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.syntax {
+            CellSyntax::Leaf(text) => write!(f, "{}", text),
+            CellSyntax::Container { open, children, close } => {
+                write!(f, "{}", open)?;
+                children.iter().try_for_each(|node| write!(f, "{}", node))?;
+                write!(f, "{}", close)
+            }
+        }
+    }
+}
+
+/// writes `nodes` one per line at `depth`. a comment on the same line as what comes before it stays there,
+/// `on_line` says whether there is something on the current line when the first node comes
+fn format_nodes(nodes: &[SyntaxNode], depth: usize, mut on_line: bool, out: &mut String) {
+    let mut newlines = 0;
+    let mut first = true;
+    for node in nodes {
+        match node {
+            SyntaxNode::Whitespace(text) => newlines += text.matches('\n').count(),
+            SyntaxNode::Comment(text) if on_line && newlines == 0 => {
+                out.push(' ');
+                out.push_str(text.trim_end());
+            }
+            node => {
+                if !first || depth > 0 {
+                    out.push('\n');
+                }
+                if !first && newlines > 1 {
+                    out.push('\n');
+                }
+                out.push_str(&INDENT.repeat(depth));
+                match node {
+                    SyntaxNode::Cell(cell) => format_cell(cell, depth, out),
+                    _ => out.push_str(node.to_string().trim_end()),
+                }
+                first = false;
+                on_line = true;
+                newlines = 0;
+            }
+        }
+    }
+}
+
+fn format_cell(node: &CellNode, depth: usize, out: &mut String) {
+    match (&node.cell, &node.syntax) {
+        (_, CellSyntax::Container { open, children, close }) => {
+            match has_comment(open) {
+                true => out.push_str(open),
+                false => out.push_str(&open.split_whitespace().collect::<Vec<_>>().join(" ")),
+            }
+            format_nodes(children, depth + 1, true, out);
+            out.push('\n');
+            out.push_str(&INDENT.repeat(depth));
+            out.push_str(&format!("}}:{}", close[2..].trim()));
+        }
+        (Cell::Task(Task { body, .. }) | Cell::Block(Block { body, .. }), CellSyntax::Leaf(text)) => {
+            let close = text.rfind("}:").unwrap_or_default();
+            let Some(head) = close.checked_sub(body.len() + 1).map(|open| &text[..open]) else {
+                return out.push_str(text);
+            };
+            match head.split_once(char::is_whitespace) {
+                Some((keyword, rest)) if !has_comment(head) => out.push_str(&format!("{} {}", keyword, rest.trim())),
+                _ => out.push_str(head.trim_end()),
+            }
+            out.push(' ');
+            out.push_str(&format_body(body, depth));
+            out.push_str(&format!(":{}", text[close + 2..].trim()));
+        }
+        (Cell::Assignment(assignment), CellSyntax::Leaf(text)) => {
+            let value = match (text.find('='), text.rfind(';')) {
+                (Some(start), Some(end)) if start < end => text[start + 1..end].trim(),
+                _ => return out.push_str(text),
+            };
+            out.push_str(&format!("let {} = {};", assignment.identifier.0, value));
+        }
+        (Cell::Import(import), CellSyntax::Leaf(text)) if !has_comment(text) => out.push_str(&import.to_string()),
+        (_, CellSyntax::Leaf(text)) => out.push_str(text),
+    }
+}
+
+/// the body of a task or block with its braces, `{ echo hi }` when it fits on a line and re-indented under
+/// the header otherwise. bodies with heredocs or multi line strings are kept as they are
+/// since their indentation is part of the text
+fn format_body(body: &str, depth: usize) -> String {
+    if !body.contains('\n') || body.trim().is_empty() {
+        return match body.trim() {
+            "" => "{}".to_string(),
+            body => format!("{{ {} }}", body),
+        };
+    }
+    if ["<<", "\"\"\"", "'''", "@'", "@\""].iter().any(|literal| body.contains(literal)) || has_multiline_string(body) {
+        return format!("{{{}}}", body);
+    }
+    format!("{{\n{}\n{}}}", reindent(body, depth + 1), INDENT.repeat(depth))
+}
+
+/// the length of the spaces and tabs `line` starts with. other whitespace, e.g `\u{3000}`, is text of the line
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+/// the lines of `body` without leading and trailing blank lines,
+/// shifted so that the least indented line starts at `depth`.
+/// text right after the opening `{` has no indentation of its own and is not taken into account
+fn reindent(body: &str, depth: usize) -> String {
+    let lines = body.lines().map(str::trim_end).collect::<Vec<_>>();
+    let (Some(first), Some(last)) = (
+        lines.iter().position(|line| !line.is_empty()),
+        lines.iter().rposition(|line| !line.is_empty()),
+    ) else {
+        return String::new();
+    };
+    let margin = lines
+        .iter()
+        .skip(1)
+        .filter(|line| !line.is_empty())
+        .map(|line| indentation(line))
+        .min()
+        .unwrap_or(0);
+    lines[first..=last]
+        .iter()
+        .enumerate()
+        .map(|(i, line)| match (line.is_empty(), i == 0 && first == 0) {
+            (true, _) => String::new(),
+            (false, true) => format!("{}{}", INDENT.repeat(depth), line.trim_start()),
+            (false, false) => {
+                let indent = indentation(line).min(margin);
+                format!("{}{}", INDENT.repeat(depth), &line[indent..])
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// whether a `"` or `'` string of `body` goes on past the end of its line.
+/// a lone apostrophe counts as one too, the body is then kept as it is
+fn has_multiline_string(body: &str) -> bool {
+    let mut quote = None;
+    let mut escaped = false;
+    for c in body.chars() {
+        match (quote, c) {
+            (Some(_), '\n') => return true,
+            (Some(_), '\\') | (Some('"'), '`') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(open), c) if c == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            _ => {}
+        }
+        escaped = false;
+    }
+    false
+}
+
+fn has_comment(text: &str) -> bool {
+    text.contains("//") || text.contains("/*")
+}
+
+/// `source` in the canonical layout. the result is parsed again and has to hold the same cells, apart from
+/// their spans and with bodies that differ only by the indentation they share. a file the formatter would
+/// change the meaning of is left alone with [`MotoError::Unformattable`]
+pub fn format_source(file: &str, source: &str) -> Result<String, MotoError> {
+    let tree = SyntaxTree::parse(file, source)?;
+    let formatted = tree.format();
+    let cells = tree.nodes.into_iter().filter_map(|node| match node {
+        SyntaxNode::Cell(node) => Some(dedented(node.cell.without_spans())),
+        _ => None,
+    });
+    match parse_file(file, &formatted) {
+        Ok(formatted_cells) if cells.eq(formatted_cells.iter().cloned().map(|cell| dedented(cell.without_spans()))) => Ok(formatted),
+        _ => Err(MotoError::Unformattable { file: file.to_string() }),
+    }
+}
+
+/// `cell` with the bodies of its tasks and blocks at any depth shifted to the left margin
+fn dedented(cell: Cell) -> Cell {
+    match cell {
+        Cell::Task(task) => Cell::Task(Task { body: reindent(&task.body, 0), ..task }),
+        Cell::Block(block) => Cell::Block(Block { body: reindent(&block.body, 0), ..block }),
+        Cell::Runtime(runtime) => Cell::Runtime(Runtime { children: runtime.children.into_iter().map(dedented).collect(), ..runtime }),
+        Cell::Package(package) => Cell::Package(Package { children: package.children.into_iter().map(dedented).collect(), ..package }),
+        cell => cell,
+    }
+}
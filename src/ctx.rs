@@ -1,201 +1,192 @@
-use crate::runtime;
-
 use super::*;
-use futures::{Future, FutureExt};
-use minimo::choice;
+use futures::Future;
 use std::collections::HashMap;
-use std::{env, fs};
-use std::fmt::Result;
-use std::io::Write;
-use std::path::Path;
+use std::{env, fs, io};
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::process::{Command, Stdio};
 use std::sync::Arc;
 
 use tokio::sync::Mutex;
 
-use minimo::*;
-
-lazy_static::lazy_static! {
-        pub static ref CTX : Ctx = Ctx::empty();
-}
-
-pub fn get_ctx() -> Ctx {
-    CTX.clone()
+#[derive(Clone)]
+///the context a run happens in: the loaded cells, the registered executors and a scope of variables.
+/// cells and executors are shared by every scope of a run, variables are not. packages and task
+/// invocations run in a child scope, variables set there stay there.
+/// `namespace` is the package the running code is defined in, see [`Ctx::within`].
+/// `environment` holds the variables given by the environment, shared like the cells
+pub struct Ctx {
+    pub variables: Arc<Mutex<HashMap<String, Atom>>>,
+    pub environment: Arc<Mutex<Environment>>,
+    pub children: Arc<Mutex<Vec<Cell>>>,
+    pub executors: Arc<Mutex<HashMap<String, Arc<dyn Executor>>>>,
+    pub namespace: Vec<String>,
+    pub parent: Option<Arc<Ctx>>,
 }
 
-pub async fn get_children() -> Vec<Cell> {
-    CTX.children.clone().lock().await.clone()
+impl std::fmt::Debug for Ctx {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ctx")
+            .field("variables", &self.variables)
+            .field("environment", &self.environment)
+            .field("children", &self.children)
+            .field("namespace", &self.namespace)
+            .field("parent", &self.parent)
+            .finish_non_exhaustive()
+    }
 }
 
+impl Ctx {
+    pub fn empty() -> Self {
+        Ctx {
+            variables: Arc::new(Mutex::new(HashMap::new())),
+            environment: Arc::new(Mutex::new(Environment::default())),
+            children: Arc::new(Mutex::new(vec![])),
+            executors: Arc::new(Mutex::new(builtin_executors())),
+            namespace: vec![],
+            parent: None,
+        }
+    }
 
+    /// a scope for a package or a task invocation. it sees the variables of this scope
+    /// and shares its cells and executors
+    pub fn child(&self) -> Self {
+        Ctx {
+            variables: Arc::new(Mutex::new(HashMap::new())),
+            environment: self.environment.clone(),
+            children: self.children.clone(),
+            executors: self.executors.clone(),
+            namespace: self.namespace.clone(),
+            parent: Some(Arc::new(self.clone())),
+        }
+    }
 
-pub async fn get_runtimes() -> Vec<Runtime> {
-    CTX.children
-        .clone()
-        .lock()
-        .await
-        .iter()
-        .filter_map(|cell| match cell {
-            Cell::Runtime(runtime) => Some(runtime.clone()),
-            _ => None,
-        })
-        .collect()
-}
+    /// makes `executor` run every task whose tail is `:name`, replacing a built in executor of the same name
+    pub async fn register_executor(&self, name: impl Into<String>, executor: Arc<dyn Executor>) {
+        self.executors.lock().await.insert(name.into(), executor);
+    }
 
-pub async fn get_packages() -> Vec<Package> {
-    CTX.children
-        .clone()
-        .lock()
-        .await
-        .iter()
-        .filter_map(|cell| match cell {
-            Cell::Package(package) => Some(package.clone()),
-            _ => None,
-        })
-        .collect()
-}
+    pub async fn get_children(&self) -> Vec<Cell> {
+        self.children.lock().await.clone()
+    }
 
-pub async fn get_tasks() -> Vec<Task> {
-   let mut tasks = vec![];
-   for tsk in CTX.children.clone().lock().await.iter() {
-       match tsk {
-           Cell::Task(task) => tasks.push(task.clone()),
-           _ => {}
-       }
+    pub async fn push_cell(&self, cell: impl Into<Cell>) {
+        self.children.lock().await.push(cell.into());
     }
-    for package in get_packages().await {
-        tasks.extend(package.tasks());
+
+    pub async fn get_runtimes(&self) -> Vec<Runtime> {
+        self.children
+            .lock()
+            .await
+            .iter()
+            .filter_map(|cell| match cell {
+                Cell::Runtime(runtime) => Some(runtime.clone()),
+                _ => None,
+            })
+            .collect()
     }
 
-    //check if moto is installed. if not diplay an option to install it
-    if is_moto_installed().await == false {
-        tasks.push(Task::new("install moto", "[:install_moto()]","moto"));
-        
+    pub async fn get_packages(&self) -> Vec<Package> {
+        self.children
+            .lock()
+            .await
+            .iter()
+            .filter_map(|cell| match cell {
+                Cell::Package(package) => Some(package.clone()),
+                _ => None,
+            })
+            .collect()
     }
-    tasks
-}
 
-pub async fn is_moto_installed() -> bool {
-    let user_dir = env::var("USERPROFILE").unwrap();
-    let moto_dir = format!("{}\\moto", user_dir);
-    let moto_exe = format!("{}\\moto.exe", moto_dir);
-    Path::new(&moto_exe).exists()
-}
+    /// every task that can be run, those of packages included, with the package it is defined in
+    pub async fn get_tasks(&self) -> Vec<Resolved<Task>> {
+        let mut tasks = vec![];
+        namespaced_tasks(&self.children.lock().await, &mut vec![], &mut tasks);
 
-pub async fn install_moto() {
-    // Copy the moto binary to user/.moto directory
-    let user_dir = env::var("USERPROFILE").unwrap();
-    let moto_dir = format!("{}\\moto", user_dir);
-    let moto_exe = format!("{}\\moto.exe",  moto_dir);
+        //check if moto is installed. if not diplay an option to install it
+        if !is_moto_installed().await {
+            tasks.push(Resolved { namespace: vec![], cell: Task::new("install moto", "[:install_moto()]","moto") });
+        }
+        tasks
+    }
 
-    // Create the moto directory
-    if !Path::new(&moto_dir).exists() {
-        fs::create_dir_all(&moto_dir).unwrap();
+    /// looks the variable up in this scope and then in the enclosing ones
+    pub async fn get_variable(&self, name: impl Into<String>) -> Option<Atom> {
+        let name = name.into().trim().to_lowercase();
+        let mut scope = Some(self);
+        while let Some(ctx) = scope {
+            if let Some(value) = ctx.variables.lock().await.get(&name) {
+                return Some(value.clone());
+            }
+            scope = ctx.parent.as_deref();
+        }
+        None
     }
 
-    // Copy the moto binary to the moto directory
-    let self_exe = env::current_exe().unwrap().to_path_buf();
-    if !Path::new(&moto_exe).exists() {
-        fs::copy(self_exe, &moto_exe).unwrap();
+    pub async fn get_variable_or_default(&self, name: impl Into<String>, default: impl Into<Atom>) -> Atom {
+        self.get_variable(name).await.unwrap_or(default.into())
     }
 
-    // Add the path to PATH
-    let path_var = "PATH";
-    let mut path_value = String::from(env::var(path_var).unwrap());
-    path_value.push_str(";");
-    path_value.push_str(&moto_dir);
-    env::set_var(path_var, &path_value);
+    /// sets the variable in this scope only
+    pub async fn set_variable(&self, name: impl Into<String>, value: Atom) {
+        let name = name.into().trim().to_lowercase();
+        print_setting_variable(&name, &value);
+        self.variables.lock().await.insert(name, value);
+    }
 }
 
-
-pub async fn get_task(name: impl Into<String>) -> Option<Task> {
-    let name = name.into();
-    CTX.children
-        .clone()
-        .lock()
-        .await
-        .iter()
-        .filter_map(|cell| match cell {
-            Cell::Task(task) => {
-                if task.identifer.matches(&name) {
-                    Some(task.clone())
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        })
-        .next()
+fn print_setting_variable(name: &str, value: &Atom) {
+    showln!(cyan_bold, "• ", gray_dim, name, cyan_bold, " » ", white, value);
 }
 
-pub async fn get_runtime(name: impl Into<String>) -> Option<Runtime> {
-    let name = name.into();
-
-    CTX.children
-        .clone()
-        .lock()
-        .await
-        .iter()
-        .filter_map(|cell| match cell {
-            Cell::Runtime(runtime) => {
-                if runtime.identifer.matches(&name) {
-                    Some(runtime.clone())
-                } else {
-                    None
-                }
-            }
-            Cell::Package(package) => package
-                .runtimes()
-                .iter()
-                .filter_map(|runtime| {
-                    if runtime.identifer.matches(&name) {
-                        Some(runtime.clone())
-                    } else {
-                        None
-                    }
-                })
-                .next(),
-            _ => None,
-        })
-        .next()
+/// where moto installs itself, `<home>/moto`. the home is `USERPROFILE` on windows and `HOME` elsewhere
+pub fn get_moto_dir() -> Result<PathBuf, MotoError> {
+    let home = env::var_os("USERPROFILE")
+        .or_else(|| env::var_os("HOME"))
+        .ok_or_else(|| MotoError::Io(io::Error::new(io::ErrorKind::NotFound, "neither USERPROFILE nor HOME is set")))?;
+    Ok(PathBuf::from(home).join("moto"))
 }
 
-pub async fn get_variable(name: impl Into<String>) -> Option<Atom> {
-    let name = name.into().trim().to_lowercase();
-    CTX.variables.clone().lock().await.get(&name).cloned()
+fn get_moto_exe(moto_dir: &Path) -> PathBuf {
+    moto_dir.join(format!("moto{}", env::consts::EXE_SUFFIX))
 }
 
-pub async fn get_variable_or_default(name: impl Into<String>, default: impl Into<Atom>) -> Atom {
-    get_variable(name).await.unwrap_or(default.into())
+pub async fn is_moto_installed() -> bool {
+    get_moto_dir().map(|moto_dir| get_moto_exe(&moto_dir).exists()).unwrap_or(false)
 }
 
-pub async fn get_function(name: impl Into<String>) -> Option<Task> {
-    let name = name.into();
-    CTX.children
-        .clone()
-        .lock()
-        .await
-        .iter()
-        .filter_map(|cell| match cell {
-            Cell::Task(function) => {
-                if function.identifer.matches(&name) {
-                    Some(function.clone())
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        })
-        .next()
+pub async fn install_moto() -> Result<(), MotoError> {
+    // Copy the moto binary to user/moto directory
+    let moto_dir = get_moto_dir()?;
+    let moto_exe = get_moto_exe(&moto_dir);
+
+    // Create the moto directory
+    if !moto_dir.exists() {
+        fs::create_dir_all(&moto_dir)?;
+    }
+
+    // Copy the moto binary to the moto directory
+    let self_exe = env::current_exe()?;
+    if !moto_exe.exists() {
+        fs::copy(self_exe, &moto_exe)?;
+    }
+
+    // Add the path to PATH
+    let path_var = "PATH";
+    let mut paths = env::var_os(path_var)
+        .map(|value| env::split_paths(&value).collect::<Vec<_>>())
+        .unwrap_or_default();
+    paths.push(moto_dir);
+    let path_value = env::join_paths(paths).map_err(io::Error::other)?;
+    env::set_var(path_var, path_value);
+    Ok(())
 }
 
-pub type Fx = Arc<fn() ->  Pin<Box<dyn Future<Output = ()> + Send>> >;
+pub type Fx = Arc<fn() ->  Pin<Box<dyn Future<Output = Result<(), MotoError>> + Send>> >;
 
 lazy_static::lazy_static!{
     pub static ref INTERNAL_FUNCTIONS: HashMap<String,  Fx> = {
         let mut map = HashMap::<String, Fx>::new();
-        map.insert("install moto".to_string(), Arc::new(|| Box::pin(install_moto())) );
+        map.insert("install_moto".to_string(), Arc::new(|| Box::pin(install_moto())) );
         map
     };
 }
@@ -205,40 +196,6 @@ pub async fn get_internal_function(name: impl Into<String>) -> Option<Fx> {
     INTERNAL_FUNCTIONS.get(&name).cloned()
 }
 
-
-
-pub async fn set_variable(name: impl Into<String>, value: Atom) {
-    let name = name.into().trim().to_lowercase();
-    print_setting_variable(&name, &value);
-    CTX.variables.clone().lock().await.insert(name, value);
-}
-
-fn print_setting_variable(name: &str, value: &Atom) {
-    showln!(cyan_bold, "• ", gray_dim, name, cyan_bold, " » ", white, value);
-}
-
-
-
-
-pub async fn push_cell(cell: impl Into<Cell>) {
-    CTX.children.clone().lock().await.push(cell.into());
-}
-
-#[derive(Clone, Debug)]
-pub struct Ctx {
-    pub variables: Arc<Mutex<HashMap<String, Atom>>>,
-    pub children: Arc<Mutex<Vec<Cell>>>,
-}
-
-impl Ctx {
-    pub fn empty() -> Self {
-        Ctx {
-            variables: Arc::new(Mutex::new(HashMap::new())),
-            children: Arc::new(Mutex::new(vec![])),
-        }
-    }
-}
-
 pub async fn get_configurations() -> Vec<AsyncChoice> {
     vec![
         AsyncChoice::new(
@@ -251,6 +208,7 @@ pub async fn get_configurations() -> Vec<AsyncChoice> {
                     // if let Some(choice) = choice {
                     //     choice.run().await;
                     // }
+                    0
                 }))
             }),
             "".to_string(),
@@ -265,6 +223,7 @@ pub async fn get_configurations() -> Vec<AsyncChoice> {
                     // if let Some(choice) = choice {
                     //     choice.run().await;
                     // }
+                    0
                 }))
             }),
             "".to_string(),
@@ -272,11 +231,36 @@ pub async fn get_configurations() -> Vec<AsyncChoice> {
     ]
 }
 
-pub fn get_local_repository_path(name: &str) -> std::path::PathBuf {
-    let current_dir = std::env::current_dir().unwrap();
+pub fn get_local_repository_path(name: &str) -> Result<PathBuf, MotoError> {
+    let current_dir = std::env::current_dir()?;
     let path = current_dir.join("repositories").join(name);
     if !path.exists() {
-        std::fs::create_dir_all(&path).unwrap();
+        std::fs::create_dir_all(&path)?;
     }
-    path
+    Ok(path)
+}
+
+#[cfg(test)]
+impl Ctx {
+    ///a fresh context holding `source` as the package `im`
+    pub async fn from_source(source: &str) -> Self {
+        let ctx = Ctx::empty();
+        ctx.push_cell(Package::new("im", parse_file("im.moto", source).unwrap())).await;
+        ctx
+    }
+}
+
+#[tokio::test]
+async fn test_child_scope() {
+    let ctx = Ctx::empty();
+    ctx.set_variable("root", Atom::from("/srv")).await;
+    let child = ctx.child();
+    child.set_variable("target", Atom::from("prod")).await;
+    child.set_variable("root", Atom::from("/tmp")).await;
+    assert_eq!(child.get_variable("target").await, Some(Atom::from("prod")));
+    assert_eq!(child.get_variable("root").await, Some(Atom::from("/tmp")));
+    // what the child sets stays in the child
+    assert_eq!(ctx.get_variable("target").await, None);
+    assert_eq!(ctx.get_variable("root").await, Some(Atom::from("/srv")));
+    assert_eq!(ctx.child().get_variable("target").await, None);
 }
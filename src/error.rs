@@ -0,0 +1,109 @@
+use super::*;
+
+#[derive(Debug, Display)]
+///everything that can go wrong while loading and running moto files
+pub enum MotoError {
+    ///a moto file could not be parsed
+    #[display("{_0}")]
+    Parse(ParseError),
+    ///a tail names a runtime that is neither built in nor defined by a `runtime` cell
+    /// e.g `:dart` without a `runtime dart {..}:moto` anywhere
+    #[display("runtime {name} not found{}", used_by.as_ref().map(|task| format!(", used by {}", task)).unwrap_or_default())]
+    RuntimeNotFound { name: String, used_by: Option<String> },
+    ///a task that was called or run by name does not exist
+    /// `scope` says where it was looked for, e.g `runtime dart defined at im.moto:12`
+    #[display("task {name} not found{}", scope.as_ref().map(|scope| format!(" in {}", scope)).unwrap_or_default())]
+    TaskNotFound { name: String, scope: Option<String> },
+    ///an unqualified name is defined by more than one package, e.g `[:install()]` with two `task install`.
+    /// `candidates` are the qualified names it could mean
+    #[display("{name} is ambiguous, it could be {}. qualify it with its package", candidates.join(" or "))]
+    Ambiguous { name: String, candidates: Vec<String> },
+    ///the process of a runtime could not be started, usually because the program is not installed
+    #[display("failed to start {program}: {source}")]
+    Spawn { program: String, source: std::io::Error },
+    ///reading or writing files, pipes or the terminal failed
+    #[display("{_0}")]
+    Io(std::io::Error),
+    ///the task ran but its process exited unsuccessfully.
+    /// `code` is `None` when the process was terminated by a signal
+    #[display("{}", match code {
+        Some(code) => format!("exited with status {}", code),
+        None => "was terminated by a signal".to_string(),
+    })]
+    NonZeroExit { code: Option<i32> },
+    ///an imported file could not be read, e.g `import "math.moto" as m` next to no `math.moto`
+    #[display("could not import {path} at {imported_at}: {source}")]
+    Import { path: String, imported_at: String, source: std::io::Error },
+    ///a file imports itself, directly or through other files. `files` is the chain of imports
+    #[display("import cycle: {}", files.join(" -> "))]
+    ImportCycle { files: Vec<String> },
+    ///a `:json` or `:yaml` block does not hold valid json or yaml once interpolated
+    #[display("block {name} defined at {defined_at} is not valid {runtime}: {reason}")]
+    InvalidBlock { name: String, runtime: String, defined_at: String, reason: String },
+    ///an expression like `[:root] + "/target"` could not be computed, e.g a variable that is not set
+    /// or `-` between two pieces of text
+    #[display("could not evaluate {expression}: {reason}")]
+    Evaluation { expression: String, reason: String },
+    ///formatting a file would change what it does, so `moto fmt` left it as it was
+    #[display("{file} could not be formatted without changing its meaning, it was left as it is")]
+    Unformattable { file: String },
+    ///`moto fmt` could not format some of the files, why was shown for each of them as it was found
+    #[display("{} could not be formatted", files.join(", "))]
+    FormatFailed { files: Vec<String> },
+    ///`moto dump` could not load some of the files, why was shown for each of them as it was found
+    #[display("{} could not be loaded", files.join(", "))]
+    LoadFailed { files: Vec<String> },
+    ///`moto fmt --check` found files that are not formatted
+    #[display("{} not formatted, run moto fmt", files.join(", "))]
+    Unformatted { files: Vec<String> },
+    ///`moto check` found problems in the scripts, they were shown as they were found
+    #[display("{problems} problem{} found", if *problems == 1 { "" } else { "s" })]
+    CheckFailed { problems: usize },
+    ///a command was called with arguments it does not take
+    #[display("usage: {usage}")]
+    Usage { usage: String },
+    ///a task uses `[:variables]` that have no value and there was no terminal to ask for them
+    #[display("{} not set, give {} like [:{}=...]", names.join(", "), if names.len() == 1 { "it" } else { "them" }, names[0])]
+    MissingVariables { names: Vec<String> },
+    ///a variable was given a value its constraint does not allow, e.g `restart` for `[:command=(start | stop)]`
+    /// or `abc` for `[:port::number]`. `reason` says what it can be
+    #[display("{value} is not a valid {name}, {reason}")]
+    InvalidValue { name: String, value: String, reason: String },
+    ///a `[:...]` in a line could not be filled in, e.g a call that failed
+    #[display("could not interpolate {segment}: {reason}")]
+    Interpolation { segment: String, reason: String },
+}
+
+impl std::error::Error for MotoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MotoError::Parse(error) => Some(error),
+            MotoError::Spawn { source, .. } => Some(source),
+            MotoError::Import { source, .. } => Some(source),
+            MotoError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for MotoError {
+    fn from(error: ParseError) -> Self {
+        MotoError::Parse(error)
+    }
+}
+
+impl From<std::io::Error> for MotoError {
+    fn from(error: std::io::Error) -> Self {
+        MotoError::Io(error)
+    }
+}
+
+impl MotoError {
+    /// the code the `moto` binary exits with when a task fails this way
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MotoError::NonZeroExit { code: Some(code) } => *code,
+            _ => 1,
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use super::*;
+use std::collections::HashMap;
+use futures::Future;
+use std::pin::Pin;
+
+impl Ctx {
+    /// computes the value of `atom` in this scope, e.g `[:root] + "/target"` gives `/home/me/target`.
+    /// variables resolve the way `[:name]` does in a task, a call like `[:version()]` runs the task
+    /// and gives its trimmed output. `&&` and `||` only evaluate their right side when they need it
+    pub fn evaluate<'a>(&'a self, atom: &'a Atom) -> Pin<Box<dyn Future<Output = Result<Atom, MotoError>> + Send + 'a>> {
+        Box::pin(async move {
+            let failed = |reason: String| MotoError::Evaluation { expression: atom.to_string(), reason };
+            match atom {
+                Atom::Array(array) => {
+                    let mut values = vec![];
+                    for value in &array.values {
+                        values.push(self.evaluate(value).await?);
+                    }
+                    Ok(Atom::array(values))
+                }
+                Atom::Object(object) => {
+                    let mut values = vec![];
+                    for (key, value) in &object.values {
+                        values.push((key.clone(), self.evaluate(value).await?));
+                    }
+                    Ok(Atom::object(values))
+                }
+                Atom::Variable(variable) => match self.resolve_variable(variable.name()).await? {
+                    Some(value) => Ok(value),
+                    None if variable.has_default() => self.evaluate(&variable.value).await,
+                    None => Err(failed(format!("{} is not set", atom))),
+                },
+                Atom::Function(function) => {
+                    let mut arguments = vec![];
+                    for argument in &function.arguments {
+                        arguments.push(Argument::positional(self.evaluate(argument).await?));
+                    }
+                    let invocation = Invocation::new(function.name().0, arguments);
+                    let output = call(self, &invocation).await?;
+                    Ok(Atom::from(output.trim().to_string()))
+                }
+                Atom::UnaryOperation(operation) => {
+                    let operand = self.evaluate(&operation.operand).await?;
+                    apply_unary(&operation.operator.value, operand).map_err(failed)
+                }
+                Atom::BinaryOperation(operation) => {
+                    let left = self.evaluate(&operation.left).await?;
+                    match (operation.operator.value.as_str(), &left) {
+                        ("&&", Atom::Boolean(false)) | ("||", Atom::Boolean(true)) => Ok(left),
+                        (operator, _) => {
+                            let right = self.evaluate(&operation.right).await?;
+                            apply_binary(operator, left, right).map_err(failed)
+                        }
+                    }
+                }
+                atom => Ok(atom.clone()),
+            }
+        })
+    }
+
+    /// the value of `let name = ...;` in this scope. the `let` can't be its own value, so when no variable
+    /// `name` is set `[:name="/srv"]` in it is its default, e.g `/srv` for `let root = [:root="/srv"];`
+    pub async fn evaluate_assignment(&self, assignment: &Assignment) -> Result<Atom, MotoError> {
+        let name = assignment.name();
+        if self.get_variable(&name).await.is_some() || self.given(&name).await.is_some() {
+            return self.evaluate(&assignment.value).await;
+        }
+        let value = with_defaults(&assignment.value, &name).map_err(|reason| MotoError::Evaluation {
+            expression: assignment.value.to_string(),
+            reason,
+        })?;
+        self.evaluate(&value).await
+    }
+}
+
+/// `atom` with every `[:name=default]` replaced by its default
+fn with_defaults(atom: &Atom, name: &str) -> Result<Atom, String> {
+    let replace = |atom: &Atom| with_defaults(atom, name);
+    Ok(match atom {
+        Atom::Variable(variable) if variable.identifier_is(name) => match variable.has_default() {
+            true => variable.value.clone(),
+            false => return Err(format!("[:{}] is the value of itself and has no default", name)),
+        },
+        Atom::Array(array) => Atom::array(array.values.iter().map(replace).collect::<Result<_, _>>()?),
+        Atom::Object(object) => Atom::object(
+            object.values.iter().map(|(key, value)| Ok((key.clone(), replace(value)?))).collect::<Result<_, String>>()?,
+        ),
+        Atom::Function(function) => {
+            let arguments = function.arguments.iter().map(replace).collect::<Result<_, _>>()?;
+            Atom::Function(Box::new(Function::new(function.name().0, arguments)))
+        }
+        Atom::UnaryOperation(operation) => Atom::unary_operation(&operation.operator.value, replace(&operation.operand)?),
+        Atom::BinaryOperation(operation) => {
+            Atom::binary_operation(replace(&operation.left)?, &operation.operator.value, replace(&operation.right)?)
+        }
+        atom => atom.clone(),
+    })
+}
+
+/// where an assignment is in the tree of cells: the indices leading to it, its namespace
+/// and the runtime it belongs to, if any
+struct Located {
+    path: Vec<usize>,
+    namespace: Vec<String>,
+    runtime: Option<Vec<usize>>,
+}
+
+fn locate_assignments(cells: &[Cell], path: &mut Vec<usize>, namespace: &mut Vec<String>, runtime: Option<&[usize]>, found: &mut Vec<Located>) {
+    for (i, cell) in cells.iter().enumerate() {
+        path.push(i);
+        match cell {
+            Cell::Assignment(_) => found.push(Located {
+                path: path.clone(),
+                namespace: namespace.clone(),
+                runtime: runtime.map(<[usize]>::to_vec),
+            }),
+            Cell::Runtime(runtime) => {
+                let at = path.clone();
+                locate_assignments(&runtime.children, path, namespace, Some(&at), found);
+            }
+            Cell::Package(package) => {
+                namespace.push(package.name());
+                locate_assignments(&package.children, path, namespace, None, found);
+                namespace.pop();
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+}
+
+fn cell_at<'a>(cells: &'a mut [Cell], path: &[usize]) -> Option<&'a mut Cell> {
+    let (first, rest) = path.split_first()?;
+    let cell = cells.get_mut(*first)?;
+    match (cell, rest.is_empty()) {
+        (cell, true) => Some(cell),
+        (Cell::Package(package), false) => cell_at(&mut package.children, rest),
+        (Cell::Runtime(runtime), false) => cell_at(&mut runtime.children, rest),
+        _ => None,
+    }
+}
+
+impl Ctx {
+    /// evaluates every `let` of the loaded cells in the package it is defined in and keeps the value
+    /// in place of the expression, so `[:greeting]` is computed once and not on every use.
+    /// the `let`s of a runtime see the ones before them. a `let` that calls a task, e.g `let v = [:version()];`,
+    /// or one using something that isn't known yet, like a parameter of the task that uses it,
+    /// keeps its expression and is evaluated where it is used
+    pub async fn evaluate_assignments(&self) -> Result<(), MotoError> {
+        let mut found = vec![];
+        locate_assignments(&self.children.lock().await, &mut vec![], &mut vec![], None, &mut found);
+
+        let mut runtime_scopes = HashMap::<Vec<usize>, Ctx>::new();
+        for Located { path, namespace, runtime } in found {
+            let (scope, in_runtime) = match runtime {
+                Some(runtime) => (runtime_scopes.entry(runtime).or_insert_with(|| self.within(namespace)).clone(), true),
+                None => (self.within(namespace), false),
+            };
+            let Some(Cell::Assignment(assignment)) = cell_at(&mut self.children.lock().await, &path).cloned() else {
+                continue;
+            };
+            if !scope.can_evaluate(&assignment).await? {
+                continue;
+            }
+            let value = scope.evaluate_assignment(&assignment).await?;
+            if let Some(Cell::Assignment(assignment)) = cell_at(&mut self.children.lock().await, &path) {
+                assignment.value = value.clone();
+            }
+            // only for the `let`s after it, the runtime sets its variables again when it runs
+            if in_runtime && scope.given(&assignment.name()).await.is_none() {
+                scope.variables.lock().await.insert(assignment.name().to_lowercase(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// whether `assignment` can be evaluated without running anything: it calls no task and every variable
+    /// it reads is set, has a default or is a `let` that already holds its value
+    async fn can_evaluate(&self, assignment: &Assignment) -> Result<bool, MotoError> {
+        if has_calls(&assignment.value) {
+            return Ok(false);
+        }
+        let mut variables = vec![];
+        read_variables(&assignment.value, &mut variables);
+        for variable in variables {
+            let name = variable.name();
+            if name.starts_with("env.") || self.get_variable(&name).await.is_some() || self.given(&name).await.is_some() {
+                continue;
+            }
+            if variable.identifier_is(assignment.name()) {
+                match variable.has_default() {
+                    true => continue,
+                    false => return Ok(false),
+                }
+            }
+            match self.find_value(&name).await? {
+                Some(Resolved { cell: Cell::Assignment(assignment), .. }) if is_computed(&assignment.value) => {}
+                None if variable.has_default() => {}
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// the variables `atom` reads, the ones in defaults included
+fn read_variables<'a>(atom: &'a Atom, into: &mut Vec<&'a Variable>) {
+    match atom {
+        Atom::Variable(variable) => {
+            into.push(variable);
+            read_variables(&variable.value, into);
+        }
+        Atom::Array(array) => array.values.iter().for_each(|value| read_variables(value, into)),
+        Atom::Object(object) => object.values.iter().for_each(|(_, value)| read_variables(value, into)),
+        Atom::Function(function) => function.arguments.iter().for_each(|argument| read_variables(argument, into)),
+        Atom::UnaryOperation(operation) => read_variables(&operation.operand, into),
+        Atom::BinaryOperation(operation) => {
+            read_variables(&operation.left, into);
+            read_variables(&operation.right, into);
+        }
+        _ => {}
+    }
+}
+
+/// whether `atom` is a value with nothing left to compute
+fn is_computed(atom: &Atom) -> bool {
+    match atom {
+        Atom::Array(array) => array.values.iter().all(is_computed),
+        Atom::Object(object) => object.values.iter().all(|(_, value)| is_computed(value)),
+        Atom::Variable(_) | Atom::Function(_) | Atom::UnaryOperation(_) | Atom::BinaryOperation(_) => false,
+        _ => true,
+    }
+}
+
+/// `-` of a number or `!` of a boolean
+pub fn apply_unary(operator: &str, operand: Atom) -> Result<Atom, String> {
+    match (operator, operand) {
+        ("-", Atom::Number(value)) => Ok(Atom::Number(-value)),
+        ("!", Atom::Boolean(value)) => Ok(Atom::Boolean(!value)),
+        (operator, operand) => Err(format!("`{}` does not work on {}", operator, kind(&operand))),
+    }
+}
+
+/// the operator between two values. `+` adds numbers, joins arrays and joins anything with text,
+/// comparisons work on numbers and on text, `&&` and `||` need booleans
+pub fn apply_binary(operator: &str, left: Atom, right: Atom) -> Result<Atom, String> {
+    use std::cmp::Ordering;
+    let mismatch = |left: &Atom, right: &Atom| format!("`{}` does not work on {} and {}", operator, kind(left), kind(right));
+    let ordering = |left: &Atom, right: &Atom| match (left, right) {
+        (Atom::Number(a), Atom::Number(b)) => a.partial_cmp(b).ok_or_else(|| mismatch(left, right)),
+        (Atom::String(a), Atom::String(b)) => Ok(a.cmp(b)),
+        _ => Err(mismatch(left, right)),
+    };
+
+    match operator {
+        "+" => match (&left, &right) {
+            (Atom::Number(a), Atom::Number(b)) => Ok(Atom::Number(a + b)),
+            (Atom::Array(a), Atom::Array(b)) => Ok(Atom::array(a.values.iter().chain(&b.values).cloned().collect())),
+            (Atom::String(_), _) | (_, Atom::String(_)) => Ok(Atom::String(format!("{}{}", left, right))),
+            _ => Err(mismatch(&left, &right)),
+        },
+        "-" | "*" | "/" => match (&left, &right) {
+            (Atom::Number(_), Atom::Number(b)) if operator == "/" && *b == 0.0 => Err("division by zero".to_string()),
+            (Atom::Number(a), Atom::Number(b)) => Ok(Atom::Number(match operator {
+                "-" => a - b,
+                "*" => a * b,
+                _ => a / b,
+            })),
+            _ => Err(mismatch(&left, &right)),
+        },
+        "==" => Ok(Atom::Boolean(left == right)),
+        "!=" => Ok(Atom::Boolean(left != right)),
+        ">" => Ok(Atom::Boolean(ordering(&left, &right)? == Ordering::Greater)),
+        "<" => Ok(Atom::Boolean(ordering(&left, &right)? == Ordering::Less)),
+        ">=" => Ok(Atom::Boolean(ordering(&left, &right)? != Ordering::Less)),
+        "<=" => Ok(Atom::Boolean(ordering(&left, &right)? != Ordering::Greater)),
+        "&&" | "||" => match (&left, &right) {
+            (Atom::Boolean(a), Atom::Boolean(b)) => Ok(Atom::Boolean(if operator == "&&" { *a && *b } else { *a || *b })),
+            _ => Err(mismatch(&left, &right)),
+        },
+        operator => Err(format!("unknown operator `{}`", operator)),
+    }
+}
+
+/// how a value is called in error messages
+fn kind(atom: &Atom) -> &'static str {
+    match atom {
+        Atom::Number(_) => "a number",
+        Atom::String(_) => "text",
+        Atom::Boolean(_) => "a boolean",
+        Atom::Array(_) => "an array",
+        Atom::Object(_) => "an object",
+        Atom::Null => "null",
+        _ => "an expression",
+    }
+}
+
+#[tokio::test]
+async fn test_evaluate_assignments() {
+    let input = "let root = [:root=\"/srv\"];\nlet out = [:root] + \"/target\";\nruntime loud {\n    let version = \"3.7\";\n    let label = \"v\" + [:version];\n    task run { echo [:label] }:shell\n}:moto\nlet later = [:missing];\nlet v = [:release()];\nlet tag = \"v\" + [:v];\ntask release { echo 1.0 }:shell\n";
+    let ctx = Ctx::from_source(input).await;
+    ctx.evaluate_assignments().await.unwrap();
+    assert_eq!(ctx.resolve_variable("out").await.unwrap(), Some(Atom::from("/srv/target")));
+    let Cell::Package(package) = &ctx.get_children().await[0] else { panic!("not a package") };
+    let Cell::Runtime(runtime) = &package.children[2] else { panic!("not a runtime") };
+    let values = runtime.assignments().into_iter().map(|assignment| assignment.value).collect::<Vec<_>>();
+    assert_eq!(values, vec![Atom::from("3.7"), Atom::from("v3.7")]);
+    // lets that can't be evaluated yet keep their expression
+    let Cell::Assignment(later) = &package.children[3] else { panic!("not an assignment") };
+    assert_eq!(later.value.to_string(), "[:missing]");
+    // and so do the ones that would run a task, it runs when a task uses them
+    let Cell::Assignment(release) = &package.children[4] else { panic!("not an assignment") };
+    assert!(matches!(release.value, Atom::Function(_)));
+    let Cell::Assignment(tag) = &package.children[5] else { panic!("not an assignment") };
+    assert!(matches!(tag.value, Atom::BinaryOperation(_)));
+    assert!(ctx.check().await.unwrap().iter().all(|diagnostic| !diagnostic.message.contains("version")));
+
+    let ctx = Ctx::from_source("let ratio = 1 / 0;\n").await;
+    assert!(matches!(ctx.evaluate_assignments().await, Err(MotoError::Evaluation { .. })));
+}
@@ -0,0 +1,63 @@
+use super::*;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+///executors run the code of a task for the runtime named in its tail, e.g `:shell` or `:dart`.
+/// the built in shells, moto's internal functions and `runtime` cells are all executors.
+/// embedders can add their own, e.g a python server or a test double, with [`Ctx::register_executor`]
+#[async_trait]
+pub trait Executor: Send + Sync {
+    /// runs `code` in the scope `ctx` and returns everything it printed to stdout.
+    /// `runtime_task` is the task of a `runtime` cell the code goes through, `run` unless a call picked another
+    async fn execute(&self, ctx: &Ctx, code: &str, runtime_task: &str) -> Result<String, MotoError>;
+}
+
+impl Ctx {
+    /// the executor for a tail: a registered one first, then a `runtime` cell with that name.
+    /// registered executors take precedence over `runtime` cells, qualified tails like `:r:rust` are always cells
+    pub async fn get_executor(&self, name: impl Into<String>) -> Result<Arc<dyn Executor>, MotoError> {
+        let name = name.into();
+        if let Some(executor) = self.executors.lock().await.get(&name) {
+            return Ok(executor.clone());
+        }
+        Ok(Arc::new(self.get_runtime(name).await?))
+    }
+}
+
+///runs moto's own functions, the tasks with a `:moto` tail like `install moto`
+pub struct Internal;
+
+/// the executors every context starts with, the built in shells and moto's internal functions
+pub fn builtin_executors() -> HashMap<String, Arc<dyn Executor>> {
+    let mut executors = HashMap::<String, Arc<dyn Executor>>::new();
+    for launcher in LAUNCHERS {
+        for name in launcher.names {
+            executors.insert(name.to_string(), Arc::new(*launcher));
+        }
+    }
+    executors.insert("moto".to_string(), Arc::new(Internal));
+    executors
+}
+
+#[tokio::test]
+async fn test_register_executor() {
+    ///remembers the code it is given instead of running it
+    #[derive(Default)]
+    struct Recording(std::sync::Mutex<Vec<(String, String)>>);
+
+    #[async_trait]
+    impl Executor for Recording {
+        async fn execute(&self, _ctx: &Ctx, code: &str, runtime_task: &str) -> Result<String, MotoError> {
+            self.0.lock().unwrap().push((code.trim().to_string(), runtime_task.to_string()));
+            Ok("recorded".to_string())
+        }
+    }
+
+    let ctx = Ctx::from_source("task greet [:name] { print(\"hi\") }:python\n").await;
+    let recording = Arc::new(Recording::default());
+    ctx.register_executor("python", recording.clone()).await;
+
+    let (_, invocation) = parse_invocation("[:greet(name: bob)]").unwrap();
+    assert_eq!(call(&ctx, &invocation).await.unwrap(), "recorded");
+    assert_eq!(*recording.0.lock().unwrap(), vec![("print(\"hi\")".to_string(), "run".to_string())]);
+}
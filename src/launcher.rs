@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///how the code of a task reaches the shell
+pub enum Feed {
+    ///lines are written to stdin one at a time, each interpolated right before it is sent
+    Stdin,
+    ///the whole task is interpolated, written to a script with this extension and the path is passed last
+    File { extension: &'static str },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///describes how to start one of the built in shells
+/// e.g `bash -s` fed through stdin or `cmd /Q /C script.cmd` fed through a file
+pub struct Launcher {
+    ///the names a tail can use for this shell, e.g `:shell` or `:bash`
+    pub names: &'static [&'static str],
+    pub program: &'static str,
+    pub args: &'static [&'static str],
+    pub feed: Feed,
+}
+
+///the built in shells. adding one is adding a line here
+pub const LAUNCHERS: &[Launcher] = &[
+    Launcher { names: &["shell", "bash"], program: "bash", args: &["-s"], feed: Feed::Stdin },
+    Launcher { names: &["sh"], program: "sh", args: &["-s"], feed: Feed::Stdin },
+    Launcher { names: &["zsh"], program: "zsh", args: &["-s"], feed: Feed::Stdin },
+    Launcher { names: &["fish"], program: "fish", args: &[], feed: Feed::Stdin },
+    Launcher {
+        names: &["powershell", "ps", "pwsh"],
+        program: "pwsh",
+        args: &["-NoProfile", "-NonInteractive", "-Command", "-"],
+        feed: Feed::Stdin,
+    },
+    Launcher { names: &["cmd"], program: "cmd", args: &["/Q", "/C"], feed: Feed::File { extension: "cmd" } },
+];
+
+/// the launcher a tail like `:ps` refers to
+pub fn get_launcher(name: &str) -> Option<&'static Launcher> {
+    LAUNCHERS.iter().find(|launcher| launcher.names.contains(&name))
+}
+
+impl Launcher {
+    /// the command to spawn, `script` is the file the code was written to in [`Feed::File`] mode
+    pub fn command(&self, script: Option<&Path>) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new(self.program);
+        command.args(self.args);
+        if let Some(script) = script {
+            command.arg(script);
+        }
+        command
+    }
+
+    /// a fresh script path in the temp directory for [`Feed::File`] launchers
+    pub fn script_path(&self) -> Option<PathBuf> {
+        static SCRIPTS: AtomicUsize = AtomicUsize::new(0);
+        match self.feed {
+            Feed::Stdin => None,
+            Feed::File { extension } => {
+                let count = SCRIPTS.fetch_add(1, Ordering::Relaxed);
+                let name = format!("moto-{}-{}.{}", std::process::id(), count, extension);
+                Some(std::env::temp_dir().join(name))
+            }
+        }
+    }
+}
@@ -1,10 +1,8 @@
-use nom::combinator::complete;
-use nom::InputLength;
-
 pub use menu::*;
 pub mod menu;
 pub use ast::*;
 pub use derive_more::{Display, From,Error};
+pub use serde::{Deserialize, Serialize};
 pub use minimo::*;
 pub mod ast;
 
@@ -17,17 +15,52 @@ pub mod ctx;
 pub use runtime::*;
 pub mod runtime;
 
+pub use launcher::*;
+pub mod launcher;
+
+pub use executor::*;
+pub mod executor;
+
+pub use namespace::*;
+pub mod namespace;
+
+pub use loader::*;
+pub mod loader;
+
+pub use eval::*;
+pub mod eval;
+
+pub use cst::*;
+pub mod cst;
+
+pub use check::*;
+pub mod check;
+
+pub use commands::*;
+pub mod commands;
+
+pub use vars::*;
+pub mod vars;
+
+pub use prompt::*;
+pub mod prompt;
+
+pub mod lsp;
+
+pub use error::*;
+pub mod error;
+
 pub use repository::*;
 pub mod repository {
+    //! repository
+    //! repository is a registrable git repository local / remote (github, gitlab, bitbucket) with some predefined runtimes and tasks
+    //! by adding a repository to the context, the user can access the runtimes and tasks defined in the repository
+    //! moto comes with a default repository that contains some predefined runtimes and tasks [github.com/moniverse/core]
+    //! here common runtimes like rust,dart,javascript,csharp,python,go etc are defined which can be used right out of the box by the user
+    //! the user can also add their own repository to the context
     use tokio::fs;
 
     use super::*;
-    ///! repository
-    ///! repository is a registrable git repository local / remote (github, gitlab, bitbucket) with some predefined runtimes and tasks
-    ///! by adding a repository to the context, the user can access the runtimes and tasks defined in the repository
-    ///! moto comes with a default repository that contains some predefined runtimes and tasks [github.com/moniverse/core]
-    ///! here common runtimes like rust,dart,javascript,csharp,python,go etc are defined which can be used right out of the box by the user
-    ///! the user can also add their own repository to the context
 
     #[derive(Debug, Display, From, Clone)]
     #[display( "{}", name)]
@@ -49,61 +82,57 @@ pub mod repository {
             self.url.clone()
         }
 
-        pub async fn load_cells(&self) {
+        pub async fn load_cells(&self, ctx: &Ctx) -> Result<(), MotoError> {
             //if the path is local, look at all the moto files and load all the cells into the context
             //if the path is remote, clone the repository and then load all the cells into the context
 
-            let path = ctx::get_local_repository_path(&self.name);
+            let path = ctx::get_local_repository_path(&self.name)?;
             
             if !path.exists() {
-                self.clone_to(&path).await
+                self.clone_to(&path).await?;
             }
 
-             match fs::read_dir(&path).await {
-                Ok(mut dir) => {
-                    while let Ok(Some(entry)) = dir.next_entry().await {
-                        let path = entry.path();
-                        if path.extension().unwrap_or_default() == "moto" {
-                            let content = fs::read_to_string(&path).await.unwrap();
-                            let cells = parser::parse_cells(&content);
-                             match cells {
-                                Ok((_, cells)) => {
-                                    for cell in cells {
-                                        ctx::push_cell(cell).await;
-                                    }
-                                }
-                                Err(e) => {
-                                    println!("Error: {:?}", e);
-                                }
-                            }
-                        }
+            let mut loader = Loader::default();
+            let mut dir = fs::read_dir(&path).await?;
+            while let Some(entry) = dir.next_entry().await? {
+                let path = entry.path();
+                if path.extension().unwrap_or_default() == "moto" {
+                    for cell in loader.load(&path)? {
+                        ctx.push_cell(cell).await;
                     }
                 }
-                Err(e) => {
-                    println!("Error: {:?}", e);
-                }
             }
+            Ok(())
         }
 
 
-        pub async fn clone_to(&self, path: &std::path::Path) {
-            let _ = fs::create_dir_all(path).await;
-            let _ = tokio::process::Command::new("git")
-                .args(&["clone", &self.url, path.to_str().unwrap()])
-                .output()
-                .await;
+        pub async fn clone_to(&self, path: &std::path::Path) -> Result<(), MotoError> {
+            fs::create_dir_all(path).await?;
+            let mut git = tokio::process::Command::new("git");
+            git.arg("clone").arg(&self.url).arg(path);
+            run_git(git).await
         }
 
-        pub async fn pull(&self) {
-            let path = ctx::get_local_repository_path(&self.name);
-            let _ = tokio::process::Command::new("git")
-                .current_dir(&path)
-                .args(&["pull"])
-                .output()
-                .await;
+        pub async fn pull(&self) -> Result<(), MotoError> {
+            let path = ctx::get_local_repository_path(&self.name)?;
+            let mut git = tokio::process::Command::new("git");
+            git.current_dir(&path).arg("pull");
+            run_git(git).await
         }
     }
+
+    async fn run_git(mut git: tokio::process::Command) -> Result<(), MotoError> {
+        let output = git
+            .output()
+            .await
+            .map_err(|source| MotoError::Spawn { program: "git".into(), source })?;
+        if !output.status.success() {
+            return Err(MotoError::NonZeroExit { code: output.status.code() });
+        }
+        Ok(())
+    }
 }
 
 
 
+
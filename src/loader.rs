@@ -0,0 +1,116 @@
+use super::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default)]
+///loads moto files along with the files they import.
+/// every file is read and parsed once no matter how often it is imported,
+/// a file that ends up importing itself is an [`MotoError::ImportCycle`]
+pub struct Loader {
+    loaded: HashMap<PathBuf, Vec<Cell>>,
+    loading: Vec<PathBuf>,
+    imported: HashSet<PathBuf>,
+    sources: HashMap<PathBuf, String>,
+}
+
+impl Loader {
+    /// makes the file at `path` load from `source` instead of the disk, e.g a file being edited and not saved yet
+    pub fn set_source(&mut self, path: &Path, source: String) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.sources.insert(path, source);
+    }
+
+    fn read(&self, path: &Path) -> Result<String, MotoError> {
+        match self.sources.get(path) {
+            Some(source) => Ok(source.clone()),
+            None => Ok(fs::read_to_string(path)?),
+        }
+    }
+
+    /// the cells of the file at `path` with its imports resolved.
+    /// an aliased import becomes a package named after the alias, e.g `import math as m;` gives `m:pi`,
+    /// the cells of an import without alias take its place
+    pub fn load(&mut self, path: &Path) -> Result<Vec<Cell>, MotoError> {
+        let path = fs::canonicalize(path)?;
+        if let Some(start) = self.loading.iter().position(|loading| *loading == path) {
+            let mut files = self.loading[start..].iter().map(|file| display_path(file)).collect::<Vec<_>>();
+            files.push(display_path(&path));
+            return Err(MotoError::ImportCycle { files });
+        }
+        if let Some(cells) = self.loaded.get(&path) {
+            return Ok(cells.clone());
+        }
+
+        let content = self.read(&path)?;
+        let cells = parse_file(display_path(&path), &content)?;
+        self.loading.push(path.clone());
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let cells = self.resolve_imports(cells, &dir);
+        self.loading.pop();
+
+        let cells = cells?;
+        self.loaded.insert(path, cells.clone());
+        Ok(cells)
+    }
+
+    /// the file at `path` as a package named after it, the way `moto` shows the files of a directory
+    pub fn load_package(&mut self, path: &Path) -> Result<Package, MotoError> {
+        let path = &fs::canonicalize(path)?;
+        let cells = self.load(path)?;
+        let content = self.read(path)?;
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        Ok(Package {
+            span: Span::whole(&content, Some(&display_path(path))),
+            ..Package::new(name, cells)
+        })
+    }
+
+    /// whether another loaded file imports the file at `path`
+    pub fn is_imported(&self, path: &Path) -> bool {
+        fs::canonicalize(path).is_ok_and(|path| self.imported.contains(&path))
+    }
+
+    fn resolve_imports(&mut self, cells: Vec<Cell>, dir: &Path) -> Result<Vec<Cell>, MotoError> {
+        let mut resolved = vec![];
+        for cell in cells {
+            match cell {
+                Cell::Import(import) => {
+                    let path = dir.join(import.file());
+                    let cells = self.load(&path).map_err(|error| match error {
+                        MotoError::Io(source) => MotoError::Import {
+                            path: import.path.clone(),
+                            imported_at: import.span.to_string(),
+                            source,
+                        },
+                        error => error,
+                    })?;
+                    if let Ok(path) = fs::canonicalize(&path) {
+                        self.imported.insert(path);
+                    }
+                    match import.alias {
+                        Some(alias) => resolved.push(Cell::Package(Package {
+                            span: import.span,
+                            ..Package::new(alias, cells)
+                        })),
+                        None => resolved.extend(cells),
+                    }
+                }
+                Cell::Package(mut package) => {
+                    package.children = self.resolve_imports(package.children, dir)?;
+                    resolved.push(Cell::Package(package));
+                }
+                cell => resolved.push(cell),
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+/// `path` relative to the current directory when it is inside it, for spans and errors
+fn display_path(path: &Path) -> String {
+    let current_dir = std::env::current_dir()
+        .and_then(fs::canonicalize)
+        .unwrap_or_default();
+    path.strip_prefix(&current_dir).unwrap_or(path).display().to_string()
+}
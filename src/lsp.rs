@@ -0,0 +1,432 @@
+//! lsp
+//! the language server behind the `moto-lsp` binary, editors start it and talk json-rpc with it over stdin and stdout.
+//! a document is analysed along with the `.moto` files next to it, the way `moto` loads a directory,
+//! so names defined in other files and imports resolve like they do when a task runs
+use super::*;
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types as lsp;
+use lsp_types::Url;
+use std::path::Path;
+
+/// serves one editor until it asks the server to shut down
+pub fn run() -> Result<(), MotoError> {
+    let failed = |error: &dyn std::fmt::Display| MotoError::Io(std::io::Error::other(error.to_string()));
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = lsp::ServerCapabilities {
+        text_document_sync: Some(lsp::TextDocumentSyncCapability::Kind(lsp::TextDocumentSyncKind::FULL)),
+        definition_provider: Some(lsp::OneOf::Left(true)),
+        hover_provider: Some(lsp::HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(lsp::OneOf::Left(true)),
+        completion_provider: Some(lsp::CompletionOptions {
+            trigger_characters: Some(vec![":".to_string()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let capabilities = serde_json::to_value(capabilities).map_err(|e| failed(&e))?;
+    connection.initialize(capabilities).map_err(|e| failed(&e))?;
+
+    let mut server = Server::new()?;
+    for message in &connection.receiver {
+        let reply = match message {
+            Message::Request(request) if connection.handle_shutdown(&request).map_err(|e| failed(&e))? => break,
+            Message::Request(request) => Message::Response(server.respond(request)),
+            Message::Notification(notification) => match server.notify(notification) {
+                Some(diagnostics) => Message::Notification(diagnostics),
+                None => continue,
+            },
+            Message::Response(_) => continue,
+        };
+        connection.sender.send(reply).map_err(|e| failed(&e))?;
+    }
+    drop(connection);
+    io_threads.join().map_err(|e| failed(&e))
+}
+
+/// the documents the editor has open, with their unsaved text
+struct Server {
+    documents: HashMap<Url, String>,
+    runtime: tokio::runtime::Runtime,
+}
+
+/// a document parsed and loaded together with the files next to it
+struct Analysis {
+    ctx: Ctx,
+    source: String,
+    cells: Vec<Cell>,
+    /// the namespace the cells of the document have, the name of the file
+    namespace: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+/// what the name under the cursor refers to
+enum Reference {
+    /// a tail like `:dart` or `:r:rust`
+    Runtime(String),
+    /// `[:name]`, a `let` or a block
+    Value(String),
+    /// `[:name()]` or the name of a task
+    Task(String),
+}
+
+impl Server {
+    fn new() -> Result<Self, MotoError> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        Ok(Server { documents: HashMap::new(), runtime })
+    }
+
+    fn respond(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            "textDocument/definition" => self.handle(request, Server::definition),
+            "textDocument/hover" => self.handle(request, Server::hover),
+            "textDocument/completion" => self.handle(request, Server::completion),
+            "textDocument/documentSymbol" => self.handle(request, Server::symbols),
+            method => Err(format!("{} is not supported", method)),
+        };
+        match result {
+            Ok(result) => Response { id, result: Some(result), error: None },
+            // -32601 is json-rpc's method not found, -32602 invalid params
+            Err(message) if message.ends_with("is not supported") => Response::new_err(id, -32601, message),
+            Err(message) => Response::new_err(id, -32602, message),
+        }
+    }
+
+    fn handle<P: serde::de::DeserializeOwned, R: serde::Serialize>(
+        &self,
+        request: Request,
+        handler: fn(&Server, P) -> Option<R>,
+    ) -> Result<serde_json::Value, String> {
+        let params = serde_json::from_value(request.params).map_err(|e| e.to_string())?;
+        serde_json::to_value(handler(self, params)).map_err(|e| e.to_string())
+    }
+
+    /// keeps track of the open documents, the diagnostics of a document that changed are sent back
+    fn notify(&mut self, notification: Notification) -> Option<Notification> {
+        let uri = match notification.method.as_str() {
+            "textDocument/didOpen" => {
+                let params: lsp::DidOpenTextDocumentParams = serde_json::from_value(notification.params).ok()?;
+                self.documents.insert(params.text_document.uri.clone(), params.text_document.text);
+                params.text_document.uri
+            }
+            "textDocument/didChange" => {
+                let params: lsp::DidChangeTextDocumentParams = serde_json::from_value(notification.params).ok()?;
+                let text = params.content_changes.into_iter().last()?.text;
+                self.documents.insert(params.text_document.uri.clone(), text);
+                params.text_document.uri
+            }
+            "textDocument/didClose" => {
+                let params: lsp::DidCloseTextDocumentParams = serde_json::from_value(notification.params).ok()?;
+                self.documents.remove(&params.text_document.uri);
+                params.text_document.uri
+            }
+            _ => return None,
+        };
+        let diagnostics = self.documents.get(&uri).map(|source| diagnostics(&uri, source)).unwrap_or_default();
+        let params = lsp::PublishDiagnosticsParams { uri, diagnostics, version: None };
+        Some(Notification::new("textDocument/publishDiagnostics".to_string(), params))
+    }
+
+    fn analyze(&self, uri: &Url) -> Option<Analysis> {
+        let path = uri.to_file_path().ok()?;
+        let source = self.documents.get(uri)?.clone();
+        let cells = parse_file(&path, &source).ok()?;
+        let mut loader = Loader::default();
+        for (uri, text) in &self.documents {
+            if let Ok(path) = uri.to_file_path() {
+                loader.set_source(&path, text.clone());
+            }
+        }
+
+        let pattern = format!("{}/*.moto", glob::Pattern::escape(&path.parent()?.to_string_lossy()));
+        let mut files = glob::glob(&pattern).ok()?.filter_map(Result::ok).collect::<Vec<_>>();
+        if !files.contains(&path) {
+            files.push(path.clone());
+        }
+        let ctx = Ctx::empty();
+        let packages = files
+            .into_iter()
+            .filter_map(|file| Some((loader.load_package(&file).ok()?, file)))
+            .collect::<Vec<_>>();
+        self.runtime.block_on(async {
+            for (package, file) in packages {
+                if !loader.is_imported(&file) {
+                    ctx.push_cell(package).await;
+                }
+            }
+        });
+        let namespace = vec![path.file_stem()?.to_string_lossy().to_string()];
+        Some(Analysis { ctx, source, cells, namespace })
+    }
+
+    /// the text of `path`, from the editor when it is open
+    fn source_of(&self, path: &Path) -> Option<String> {
+        let uri = Url::from_file_path(path).ok()?;
+        match self.documents.get(&uri) {
+            Some(source) => Some(source.clone()),
+            None => std::fs::read_to_string(path).ok(),
+        }
+    }
+
+    /// where `span` is, spans of loaded files name them relative to the current directory
+    fn location(&self, span: &Span) -> Option<lsp::Location> {
+        let path = std::env::current_dir().ok()?.join(span.file.as_deref()?);
+        let source = self.source_of(&path)?;
+        Some(lsp::Location { uri: Url::from_file_path(&path).ok()?, range: range(&source, span) })
+    }
+
+    fn definition(&self, params: lsp::GotoDefinitionParams) -> Option<lsp::GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let analysis = self.analyze(&position.text_document.uri)?;
+        let offset = offset(&analysis.source, position.position);
+        let scope = analysis.ctx.within(analysis.namespace_at(offset));
+        let span = self.runtime.block_on(async {
+            match reference_at(&analysis.source, offset)? {
+                Reference::Runtime(name) => Some(scope.get_runtime(name).await.ok()?.cell.span),
+                Reference::Task(name) => Some(scope.get_task(name).await.ok()?.cell.span),
+                Reference::Value(name) => Some(scope.find_value(&name).await.ok()??.cell.span().clone()),
+            }
+        })?;
+        self.location(&span).map(lsp::GotoDefinitionResponse::Scalar)
+    }
+
+    /// a task's tail and body, over its name or a call of it
+    fn hover(&self, params: lsp::HoverParams) -> Option<lsp::Hover> {
+        let position = params.text_document_position_params;
+        let analysis = self.analyze(&position.text_document.uri)?;
+        let offset = offset(&analysis.source, position.position);
+        let Reference::Task(name) = reference_at(&analysis.source, offset)? else {
+            return None;
+        };
+        let scope = analysis.ctx.within(analysis.namespace_at(offset));
+        let task = self.runtime.block_on(scope.get_task(name)).ok()?;
+        let value = format!(
+            "task **{}** runs on `:{}`, defined at {}\n```\n{}\n```",
+            task.qualify(&task.cell.name()),
+            task.cell.runtime.0,
+            task.cell.span,
+            dedent(task.cell.body.trim_matches('\n')).trim_end(),
+        );
+        Some(lsp::Hover {
+            contents: lsp::HoverContents::Markup(lsp::MarkupContent { kind: lsp::MarkupKind::Markdown, value }),
+            range: None,
+        })
+    }
+
+    /// runtimes after `}:`, variables and tasks after `[:` and every name anywhere else
+    fn completion(&self, params: lsp::CompletionParams) -> Option<lsp::CompletionResponse> {
+        let position = params.text_document_position;
+        let analysis = self.analyze(&position.text_document.uri)?;
+        let offset = offset(&analysis.source, position.position);
+        let before = analysis.source[..offset]
+            .trim_end_matches(|c: char| c.is_ascii_alphanumeric() || "_:.".contains(c))
+            .chars()
+            .next_back();
+        let wanted = |kind: lsp::CompletionItemKind| match before {
+            Some('}') => kind == lsp::CompletionItemKind::MODULE,
+            Some('[') => kind != lsp::CompletionItemKind::MODULE,
+            _ => true,
+        };
+
+        let cells = self.runtime.block_on(analysis.ctx.get_children());
+        let mut items = vec![];
+        completions(&cells, &mut vec![], &mut items);
+        if before == Some('}') {
+            let executors = self.runtime.block_on(analysis.ctx.executors.lock()).keys().cloned().collect::<Vec<_>>();
+            items.extend(executors.into_iter().map(|name| lsp::CompletionItem {
+                label: name,
+                kind: Some(lsp::CompletionItemKind::MODULE),
+                detail: Some("built in runtime".to_string()),
+                ..Default::default()
+            }));
+        }
+        items.retain(|item| item.kind.is_some_and(wanted));
+        Some(lsp::CompletionResponse::Array(items))
+    }
+
+    fn symbols(&self, params: lsp::DocumentSymbolParams) -> Option<lsp::DocumentSymbolResponse> {
+        let source = self.documents.get(&params.text_document.uri)?;
+        let cells = parse(source).ok()?;
+        Some(lsp::DocumentSymbolResponse::Nested(symbols(source, &cells)))
+    }
+}
+
+impl Analysis {
+    /// the namespace of the package the byte `offset` is in
+    fn namespace_at(&self, offset: usize) -> Vec<String> {
+        let mut namespace = self.namespace.clone();
+        let mut cells = self.cells.as_slice();
+        while let Some(package) = cells.iter().find_map(|cell| match cell {
+            Cell::Package(package) if (package.span.start..package.span.end).contains(&offset) => Some(package),
+            _ => None,
+        }) {
+            namespace.push(package.name());
+            cells = &package.children;
+        }
+        namespace
+    }
+}
+
+/// the parse error of a document, if it has one
+fn diagnostics(uri: &Url, source: &str) -> Vec<lsp::Diagnostic> {
+    let path = uri.to_file_path().unwrap_or_default();
+    let Err(error) = parse_file(path, source) else {
+        return vec![];
+    };
+    let line = source.lines().nth(error.line.saturating_sub(1)).unwrap_or_default();
+    let character = line.chars().take(error.column.saturating_sub(1)).map(char::len_utf16).sum::<usize>();
+    let position = lsp::Position::new(error.line.saturating_sub(1) as u32, character as u32);
+    vec![lsp::Diagnostic {
+        range: lsp::Range::new(position, position),
+        severity: Some(lsp::DiagnosticSeverity::ERROR),
+        source: Some("moto".to_string()),
+        message: error.message(),
+        ..Default::default()
+    }]
+}
+
+fn reference_at(source: &str, offset: usize) -> Option<Reference> {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || "_:.".contains(c);
+    let start = source[..offset].rfind(|c: char| !is_name(c)).map(|i| i + 1).unwrap_or(0);
+    let end = source[offset..].find(|c: char| !is_name(c)).map(|i| offset + i).unwrap_or(source.len());
+    let token = &source[start..end];
+    let name = token.strip_prefix(':').map(String::from);
+    match (source[..start].chars().next_back(), name, source[end..].chars().next()) {
+        (Some('}'), Some(name), _) => Some(Reference::Runtime(name)),
+        (Some('['), Some(name), Some('(')) => Some(Reference::Task(name)),
+        (Some('['), Some(name), _) => Some(Reference::Value(name)),
+        (_, None, _) if !token.is_empty() => Some(Reference::Task(token.to_string())),
+        _ => None,
+    }
+}
+
+/// the names every package defines, with their qualified name as detail
+fn completions(cells: &[Cell], namespace: &mut Vec<String>, items: &mut Vec<lsp::CompletionItem>) {
+    for cell in cells {
+        let kind = match cell {
+            Cell::Task(_) => lsp::CompletionItemKind::FUNCTION,
+            Cell::Runtime(_) => lsp::CompletionItemKind::MODULE,
+            Cell::Assignment(_) | Cell::Block(_) => lsp::CompletionItemKind::VARIABLE,
+            Cell::Package(package) => {
+                namespace.push(package.name());
+                completions(&package.children, namespace, items);
+                namespace.pop();
+                continue;
+            }
+            _ => continue,
+        };
+        items.push(lsp::CompletionItem {
+            label: cell.name(),
+            kind: Some(kind),
+            detail: Some(qualify(namespace, &cell.name())),
+            ..Default::default()
+        });
+    }
+}
+
+#[allow(deprecated)]
+fn symbols(source: &str, cells: &[Cell]) -> Vec<lsp::DocumentSymbol> {
+    let mut symbols = vec![];
+    for cell in cells {
+        let (kind, detail, children) = match cell {
+            Cell::Package(package) => (lsp::SymbolKind::MODULE, None, symbols_of(source, &package.children)),
+            Cell::Runtime(runtime) => (lsp::SymbolKind::CLASS, None, symbols_of(source, &runtime.children)),
+            Cell::Task(task) => (lsp::SymbolKind::FUNCTION, Some(format!(":{}", task.runtime.0)), None),
+            _ => continue,
+        };
+        let range = range(source, cell.span());
+        symbols.push(lsp::DocumentSymbol {
+            name: cell.name(),
+            detail,
+            kind,
+            tags: None,
+            deprecated: None,
+            range,
+            selection_range: range,
+            children,
+        });
+    }
+    symbols
+}
+
+fn symbols_of(source: &str, cells: &[Cell]) -> Option<Vec<lsp::DocumentSymbol>> {
+    Some(symbols(source, cells)).filter(|symbols| !symbols.is_empty())
+}
+
+/// where `span` is in `source`, both of its ends
+fn range(source: &str, span: &Span) -> lsp::Range {
+    lsp::Range::new(position(source, span.start), position(source, span.end))
+}
+
+/// the lsp position of the byte `offset`, lsp counts the characters of a line in utf-16 code units
+fn position(source: &str, offset: usize) -> lsp::Position {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let character = before[line_start..].encode_utf16().count();
+    lsp::Position::new(before.matches('\n').count() as u32, character as u32)
+}
+
+/// the byte offset of an lsp position in `source`
+fn offset(source: &str, position: lsp::Position) -> usize {
+    let line_start = source.split_inclusive('\n').take(position.line as usize).map(str::len).sum::<usize>();
+    let line = source[line_start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[test]
+fn test_reference_at() {
+    let source = "task build { echo [:version] [:greet(x)] }:r:rust\n[:build()]";
+    let at = |text: &str| source.find(text).unwrap() + 1;
+    assert_eq!(reference_at(source, at("build")), Some(Reference::Task("build".to_string())));
+    assert_eq!(reference_at(source, at("version")), Some(Reference::Value("version".to_string())));
+    assert_eq!(reference_at(source, at("greet")), Some(Reference::Task("greet".to_string())));
+    assert_eq!(reference_at(source, at("r:rust")), Some(Reference::Runtime("r:rust".to_string())));
+    assert_eq!(reference_at(source, source.find('{').unwrap()), None);
+}
+
+#[test]
+fn test_offset_and_position() {
+    // é is two bytes and one utf-16 unit, 😀 four bytes and two units
+    let source = "let a = \"é😀\";\nlet b = 1;";
+    let b = source.find('b').unwrap();
+    assert_eq!(position(source, b), lsp::Position::new(1, 4));
+    assert_eq!(offset(source, lsp::Position::new(1, 4)), b);
+    let quote = source.rfind('"').unwrap();
+    assert_eq!(position(source, quote), lsp::Position::new(0, 12));
+    assert_eq!(offset(source, lsp::Position::new(0, 12)), quote);
+    // past the end of a line is its end
+    assert_eq!(offset(source, lsp::Position::new(0, 99)), source.find('\n').unwrap());
+}
+
+#[test]
+fn test_symbols() {
+    let source = "package p {\n    runtime r {\n        task run { echo }:shell\n    }:moto\n}:moto\nlet x = 1;\n";
+    let symbols = symbols(source, &parse(source).unwrap());
+    assert_eq!(symbols.len(), 1);
+    assert_eq!((symbols[0].name.as_str(), symbols[0].kind), ("p", lsp::SymbolKind::MODULE));
+    assert_eq!(symbols[0].range.start, lsp::Position::new(0, 0));
+    let runtime = &symbols[0].children.as_ref().unwrap()[0];
+    assert_eq!((runtime.name.as_str(), runtime.kind), ("r", lsp::SymbolKind::CLASS));
+    let task = &runtime.children.as_ref().unwrap()[0];
+    assert_eq!((task.name.as_str(), task.detail.as_deref()), ("run", Some(":shell")));
+    assert_eq!(task.range.start, lsp::Position::new(2, 8));
+    assert!(task.children.is_none());
+}
+
+#[test]
+fn test_diagnostics() {
+    let uri = Url::parse("file:///tmp/im.moto").unwrap();
+    assert!(diagnostics(&uri, "task a { echo }:shell\n").is_empty());
+    let diagnostics = diagnostics(&uri, "let s = \"😀\" + ;\n");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Some(lsp::DiagnosticSeverity::ERROR));
+    // at the `+`, after twelve characters that take thirteen utf-16 units
+    assert_eq!(diagnostics[0].range.start, lsp::Position::new(0, 13));
+}
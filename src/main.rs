@@ -9,25 +9,44 @@
 mod parser;
 
 
-use crossterm::terminal::{ClearType, LeaveAlternateScreen};
-use minimo::*;
 use moto::*;
-use std::{env, fs, path::Path};
 
 #[tokio::main]
 async fn main() {
-    print_banner();
-    start().await;
+    if let Err(error) = start().await {
+        match &error {
+            MotoError::Parse(error) => show_parse_error(error),
+            error => show_error(&error.to_string()),
+        }
+        std::process::exit(error.exit_code());
+    }
 }
 
-pub async fn start() {
-    moto::menu::scan().await.unwrap();
-    match moto::menu::handle_args().await {
+pub async fn start() -> Result<(), MotoError> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    // commands print nothing but their output, `moto dump` is read by other tools
+    if let Some(result) = run_command(&args).await {
+        return result;
+    }
+    print_banner();
+    let ctx = Ctx::empty();
+    moto::menu::scan(&ctx).await?;
+    let action = moto::menu::handle_command_line(&ctx).await?;
+    // after the command line variables are set, `let root = [:root=".."];` takes `[:root=/srv]`
+    ctx.evaluate_assignments().await?;
+    match action {
         Some(action) => {
-            action.run().await;
+            // `moto <task>` is used from scripts and CI, they need to see the task fail
+            let code = action.run().await;
+            if code != 0 {
+                std::process::exit(code);
+            }
         }
         None => {
-            moto::menu::display_options().await.run().await;
+            if let Some(choice) = moto::menu::display_options(&ctx).await? {
+                choice.run().await;
+            }
         }
     }
+    Ok(())
 }
@@ -1,18 +1,11 @@
 use super::*;
-use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyEventState};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use crossterm::execute;
-use crossterm::terminal::{
-    disable_raw_mode, enable_raw_mode, ClearType, EnterAlternateScreen, LeaveAlternateScreen
-};
-use futures::{Future, FutureExt};
-use std::collections::HashMap;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, ClearType};
+use futures::Future;
 use std::env;
-use std::fmt::Result;
-use std::io::Write;
 use std::pin::Pin;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::fs;
 
 const BANNER: &str = r#"
                                  __  
@@ -39,7 +32,7 @@ fn print_guidelines() {
 pub struct AsyncChoice {
     name: String,
     description: String,
-    action: Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>>>,
+    action: Arc<dyn Fn() -> Pin<Box<dyn Future<Output = i32> + Send>>>,
     file_path: String,
 }
 
@@ -47,7 +40,7 @@ impl AsyncChoice {
     pub fn new(
         name: impl Into<String>,
         description: impl Into<String>,
-        action: Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>>>,
+        action: Arc<dyn Fn() -> Pin<Box<dyn Future<Output = i32> + Send>>>,
         file_path: impl Into<String>,
     ) -> Self {
         AsyncChoice {
@@ -58,9 +51,10 @@ impl AsyncChoice {
         }
     }
 
-    pub async fn run(&self) {
+    /// runs the choice and returns the code the process should exit with, 0 when it went well
+    pub async fn run(&self) -> i32 {
         let action = self.action.clone();
-        action().await;
+        action().await
     }
 
     pub fn get_title(&self) -> &str {
@@ -84,32 +78,31 @@ impl PartialEq for AsyncChoice {
 
 
 
-pub async fn scan() -> std::io::Result<()> {
-    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+/// loads every `.moto` file of the current directory into `ctx`, each file becomes a package named after it.
+/// files imported by another file of the directory are only reachable through that file
+pub async fn scan(ctx: &Ctx) -> Result<(), MotoError> {
+    let current_dir = std::env::current_dir()?;
+    ctx.load_environment(&current_dir).await?;
 
-    let pattern = format!("{}/*.moto", current_dir.to_str().unwrap());
-    for entry in glob::glob(&pattern).expect("Failed to read glob pattern") {
+    let pattern = format!("{}/*.moto", glob::Pattern::escape(&current_dir.to_string_lossy()));
+    let entries = glob::glob(&pattern).map_err(|e| MotoError::Io(std::io::Error::other(e)))?;
+    let mut loader = Loader::default();
+    let mut packages = vec![];
+    for entry in entries {
         match entry {
-            Ok(path) => {
-                let content = fs::read_to_string(path.clone()).await?;
-                let script = ast::parse(&content);
-                let package_name =  //filename without extension
-                    path.file_stem().unwrap_or_default().to_str().unwrap_or_default();
-
-                match script {
-                    Ok(script) => {
-                       push_cell( Package::new(package_name, script)).await;
-            
-                    }
-                    Err(e) =>  {
-                        eprintln!("Error parsing file: {:?}", e);
-                        // set(Package::new(package_name, vec![]))
-                    }
-                }
-            }
+            Ok(path) => match loader.load_package(&path) {
+                Ok(package) => packages.push((path, package)),
+                Err(MotoError::Parse(e)) => show_parse_error(&e),
+                Err(e) => show_error(&e.to_string()),
+            },
             Err(e) => eprintln!("Error reading file: {:?}", e),
         }
     }
+    for (path, package) in packages {
+        if !loader.is_imported(&path) {
+            ctx.push_cell(package).await;
+        }
+    }
     Ok(())
 }
 
@@ -119,8 +112,6 @@ pub async fn scan() -> std::io::Result<()> {
 /// user can also search by typing the name of the choice and the list will be filtered moving the selected item to the top
 /// the search string is also diplayed in the bottom of the menu. backspace can be used to delete the last character
 /// user can exit the menu by pressing the escape key.
-
-
 fn filter_choices(choices: &[AsyncChoice], filter: &str) -> Vec<AsyncChoice> {
     choices
         .iter()
@@ -139,18 +130,17 @@ fn filter_choices(choices: &[AsyncChoice], filter: &str) -> Vec<AsyncChoice> {
 /// and a list of choices that the user can select from using the arrow keys and enter
 /// user can also search by typing the name of the choice and the list will be filtered moving the selected item to the top
 /// the search string is also diplayed in the bottom of the menu. backspace can be used to delete the last character
-/// user can exit the menu by pressing the escape key.
-
+/// user can leave the menu by pressing the escape key, nothing is selected then.
 pub fn display_selection_menu(
     header: &str,
     choices: &[AsyncChoice],
     configurations: &[AsyncChoice],
-) -> Option<AsyncChoice> {
-    enable_raw_mode().expect("Failed to enable raw mode");
+) -> Result<Option<AsyncChoice>, MotoError> {
+    enable_raw_mode()?;
     execute!(
         std::io::stdout(),
         crossterm::cursor::Hide,
-    ).expect("Failed to clear terminal");
+    )?;
 
 
     print_guidelines();
@@ -160,9 +150,9 @@ pub fn display_selection_menu(
     let mut filtered_choices = choices.to_vec();
     let mut is_config_mode = false;
 
-    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode);
+    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode)?;
     loop {
-        if let Event::Key(key_event) = event::read().expect("Failed to read event") {
+        if let Event::Key(key_event) = event::read()? {
             if key_event.kind != KeyEventKind::Press {
                 continue;
             }
@@ -170,39 +160,32 @@ pub fn display_selection_menu(
                 KeyCode::Char('c')
                     if key_event.modifiers.contains(event::KeyModifiers::CONTROL) =>
                 {
-                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode);
+                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode)?;
                     break;
                 }
-                KeyCode::Up => {
-                    if selected > 0 {
-                        selected -= 1;
-                        print_menu(header, &filtered_choices, selected, &search_text, is_config_mode);
-                    }
+                KeyCode::Up if selected > 0 => {
+                    selected -= 1;
+                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode)?;
                 }
-                KeyCode::Down => {
-                    if selected < filtered_choices.len() - 1 {
-                        selected += 1;
-                        print_menu(header, &filtered_choices, selected, &search_text, is_config_mode);
-                    }
+                KeyCode::Down if selected + 1 < filtered_choices.len() => {
+                    selected += 1;
+                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode)?;
                 }
-                KeyCode::Enter => {
+                KeyCode::Enter if selected < filtered_choices.len() => {
                     execute!(
                         std::io::stdout(),
                         crossterm::cursor::Show,
                         crossterm::terminal::Clear(ClearType::FromCursorDown),
-                    ).expect("Failed to clear terminal");
-                    let title = filtered_choices[selected].get_title();
-                    showln!(yellow_bold, "╭─ ", gray_dim, "running ", yellow_bold, title, yellow_bold, " ─",yellow_bold,"─".repeat(47 - title.len()));
-                    // showln!(yellow_bold, "↓");
-                    disable_raw_mode().expect("Failed to disable raw mode");
-                    return Some(filtered_choices[selected].clone());
+                    )?;
+                    disable_raw_mode()?;
+                    return Ok(Some(filtered_choices[selected].clone()));
                 }
                 KeyCode::Char(':') => {
                     is_config_mode = true;
                     search_text.clear();
                     filtered_choices = configurations.to_vec();
                     selected = 0;
-                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode);
+                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode)?;
                 }
                 KeyCode::Char(c) => {
                     search_text.push(c);
@@ -212,7 +195,7 @@ pub fn display_selection_menu(
                         filter_choices(choices, &search_text)
                     };
                     selected = 0;
-                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode);
+                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode)?;
                 }
                 KeyCode::Backspace => {
                     search_text.pop();
@@ -227,7 +210,7 @@ pub fn display_selection_menu(
                         };
                     }
                     selected = 0;
-                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode);
+                    print_menu(header, &filtered_choices, selected, &search_text, is_config_mode)?;
                 }
                 KeyCode::Esc => {
                     //clear everything below the menu
@@ -235,24 +218,26 @@ pub fn display_selection_menu(
                         std::io::stdout(),
                         crossterm::cursor::Show,
                         crossterm::terminal::Clear(ClearType::FromCursorDown),
-                    ).expect("Failed to clear terminal");
-                    //terminate the program
-                    std::process::exit(0);
+                    )?;
+                    //the caller decides what leaving means, a prompt for a variable fails
+                    disable_raw_mode()?;
+                    return Ok(None);
                 }
                 _ => {}
             }
         }
     }
 
-    None
+    disable_raw_mode()?;
+    Ok(None)
 }
 
 
-fn print_menu(header: &str, choices: &[AsyncChoice], selected: usize, search_text: &str, is_config_mode: bool) {
+fn print_menu(header: &str, choices: &[AsyncChoice], selected: usize, search_text: &str, is_config_mode: bool) -> Result<(), MotoError> {
     execute!(
         std::io::stdout(),
         crossterm::terminal::Clear(ClearType::FromCursorDown),
-    ).expect("Failed to clear terminal");
+    )?;
 
     if is_config_mode {
         showln!(purple_bold, "╭─ ", white_bold, ":configurations", purple_bold, " ─");
@@ -334,73 +319,128 @@ fn print_menu(header: &str, choices: &[AsyncChoice], selected: usize, search_tex
     execute!(
         std::io::stdout(),
         crossterm::cursor::MoveUp(move_to),
-    ).expect("Failed to clear terminal");
+    )?;
+    Ok(())
 }
 
-pub async fn display_options() ->  AsyncChoice {
-    let  choices = get_tasks().await.into_iter().map(|task| task.into()).collect::<Vec<AsyncChoice>>()
-                    .into_iter().chain(default_choices().into_iter()).collect::<Vec<AsyncChoice>>();
+/// the task or setting picked from the main menu, `None` when it was left with escape
+pub async fn display_options(ctx: &Ctx) -> Result<Option<AsyncChoice>, MotoError> {
+    let  choices = ctx.get_tasks().await.into_iter().map(|task| AsyncChoice::from_task(&ctx.within(task.namespace), task.cell)).collect::<Vec<AsyncChoice>>()
+                    .into_iter().chain(get_runnable_packages(ctx).await.into_iter().map(|package| AsyncChoice::from_package(ctx, package)))
+                    .chain(default_choices()).collect::<Vec<AsyncChoice>>();
     let configurations = get_configurations().await;
-    let mut selection = None;
-    while selection.is_none() {
-        selection = display_selection_menu("what do you want to do?", &choices, &configurations);
+    match display_selection_menu("what do you want to do?", &choices, &configurations)? {
+        Some(selection) => {
+            let title = selection.get_title();
+            showln!(yellow_bold, "╭─ ", gray_dim, "running ", yellow_bold, title, yellow_bold, " ─",yellow_bold,"─".repeat(47usize.saturating_sub(title.len())));
+            Ok(Some(selection))
+        }
+        None => Ok(None),
     }
-    selection.unwrap()
 }
 
 /// handling args
 /// moto <task_name> will run the task with the name <task_name>.
-/// if no task with the name <task_name> is found, the user will be prompted to select a task from the list of available tasks.
+/// moto <words..> will run the first task whose signature matches all of the words, e.g `moto please open firefox.exe`
+/// runs `task (please)? open (browser | [:path]) {..}:shell` with `[:path]` set to `firefox.exe`.
+/// if no task matches, the user will be prompted to select a task from the list of available tasks.
 /// moto <task_name> [:vname = whatever the content until next occurance of `[:` or eof 
-/// this will allow users to provide long sentences as variables without having to use quotes
-pub async fn handle_args() -> Option<AsyncChoice> {
+/// this will allow users to provide long sentences as variables without having to use quotes.
+/// variables given this way are set in `ctx`, words captured by a signature only in the scope of the task.
+/// the first word can be qualified with a package, e.g `moto rust:install`. a task name that
+/// matches tasks of several packages is an error, unless one of them is defined outside any package
+pub async fn handle_command_line(ctx: &Ctx) -> Result<Option<AsyncChoice>, MotoError> {
     let args: Vec<String> = env::args().collect();
     if args.len() > 1 {
-        showln!(gray_dim, "searching for ", yellow_bold, &args[1], gray_dim, "...");
-        let (task_name, variables) = parse_args(&args);
-        let mut matched = None;
-        
-        for task in get_tasks().await {
-            if task.name().to_lowercase().trim() == task_name {
-                matched = Some(task);
-                break;
+        let (mut words, variables) = parse_args(&args[1..]);
+        let command = words.join(" ");
+        showln!(gray_dim, "searching for ", yellow_bold, &command, gray_dim, "...");
+
+        let mut qualifier = vec![];
+        if let Some(first) = words.first_mut() {
+            (qualifier, *first) = split_qualified(first);
+        }
+        let mut candidates = vec![];
+        for task in ctx.get_tasks().await {
+            if !task.namespace.ends_with(&qualifier) {
+                continue;
+            }
+            if let Some(bindings) = task.cell.matches(&words) {
+                candidates.push((task, bindings));
             }
         }
+        let matched = match candidates.iter().position(|(task, _)| task.namespace.is_empty()) {
+            Some(index) => Some(candidates.swap_remove(index)),
+            None if candidates.len() > 1 => {
+                return Err(MotoError::Ambiguous {
+                    name: command,
+                    candidates: candidates.iter().map(|(task, _)| task.qualify(&task.cell.name())).collect(),
+                })
+            }
+            None => candidates.pop(),
+        };
         for var in variables {
             showln!(cyan_bold, &var.name(), gray_dim, " = ", white, &var.get_value_str());
-            set_variable(var.name(), var.get_value()).await;
+            ctx.set_variable(var.name(), var.get_value()).await;
         }
 
         divider_vibrant();
 
-        if let Some(task) = matched {
-            let title = task.name();
-            showln!(yellow_bold, "╭─ ", gray_dim, "running ", yellow_bold, title, yellow_bold, " ─",yellow_bold,"─".repeat(47 - title.len()));
-            return Some(task.into());
+        if let Some((task, bindings)) = matched {
+            let scope = ctx.within(task.namespace);
+            let task = task.cell;
+            for (name, value) in bindings {
+                scope.set_variable(name, value.into()).await;
+            }
+            let title = task.header();
+            showln!(yellow_bold, "╭─ ", gray_dim, "running ", yellow_bold, title, yellow_bold, " ─",yellow_bold,"─".repeat(47usize.saturating_sub(title.len())));
+            Ok(Some(AsyncChoice::from_task(&scope, task)))
+        } else if let Some(package) = get_runnable_packages(ctx).await.into_iter().find(|package| {
+            words.len() == 1 && package.name().eq_ignore_ascii_case(&words[0])
+        }) {
+            let title = package.name();
+            showln!(yellow_bold, "╭─ ", gray_dim, "running ", yellow_bold, title, yellow_bold, " ─",yellow_bold,"─".repeat(47usize.saturating_sub(title.len())));
+            Ok(Some(AsyncChoice::from_package(ctx, package)))
         } else {
-            showln!(orange_bold, "could not find ", gray_dim,"a task with the name ", yellow_bold, &task_name, gray_dim, "... ");
-            return None;
+            showln!(orange_bold, "could not find ", gray_dim,"a task matching ", yellow_bold, &command, gray_dim, "... ");
+            Ok(None)
         }
     } else {
-        None
+        Ok(None)
     }
 
 
 }
 
 
-impl From<Task> for AsyncChoice {
-    fn from(task: Task) -> Self {
-        let name = task.name();
-        let description = format!("{}", task.runtime());
-        let file_path = env::current_dir().unwrap_or_default().to_str().unwrap_or_default().to_string();
+impl AsyncChoice {
+    /// a choice that runs `task` in the scope `ctx`.
+    /// tasks of nested or imported packages are listed under their namespace, e.g `m:install`,
+    /// leaving out the package of the file itself
+    pub fn from_task(ctx: &Ctx, task: Task) -> Self {
+        let name = match ctx.namespace.get(1..) {
+            Some(namespace) if !namespace.is_empty() => format!("{}:{}", namespace.join(":"), task.header()),
+            _ => task.header(),
+        };
+        let description = task.runtime();
+        let file_path = if task.span.is_located() {
+            format!("defined at {}", task.span)
+        } else {
+            env::current_dir().unwrap_or_default().to_str().unwrap_or_default().to_string()
+        };
 
+        let ctx = ctx.clone();
         AsyncChoice::new(name, description,  Arc::new(move || {
             let task = task.clone();
-            let code = task.get_code();
-            let runtime = task.runtime();
+            let ctx = ctx.clone();
             Pin::from(Box::new(async move {
-                execute(code, runtime, "run").await.unwrap();
+                match execute_task(&ctx, &task, "run").await {
+                    Ok(_) => 0,
+                    Err(e) => {
+                        show_error(&format!("{} (task {} defined at {})", e, task.name(), task.span));
+                        e.exit_code()
+                    }
+                }
             }))
         }), file_path)
     }
@@ -408,11 +448,45 @@ impl From<Task> for AsyncChoice {
 
 
 
-fn parse_args(args: &[String]) -> (String, Vec<Variable>) {
+/// packages that invoke tasks, these can be run on their own
+async fn get_runnable_packages(ctx: &Ctx) -> Vec<Package> {
+    ctx.get_packages()
+        .await
+        .into_iter()
+        .filter(|package| !package.invocations().is_empty())
+        .collect()
+}
+
+impl AsyncChoice {
+    /// a choice that runs the invocations of `package`
+    pub fn from_package(ctx: &Ctx, package: Package) -> Self {
+        let name = package.name();
+        let description = format!("package, runs {} tasks", package.invocations().len());
+        let file_path = format!("defined at {}", package.span);
+
+        let ctx = ctx.clone();
+        AsyncChoice::new(name, description, Arc::new(move || {
+            let package = package.clone();
+            let ctx = ctx.clone();
+            Pin::from(Box::new(async move {
+                match execute_package(&ctx, &package).await {
+                    Ok(_) => 0,
+                    Err(e) => {
+                        show_error(&format!("{} (package {} defined at {})", e, package.name(), package.span));
+                        e.exit_code()
+                    }
+                }
+            }))
+        }), file_path)
+    }
+}
+
+/// splits the arguments, the program name left out, into the words of the command line and `[:name=value]` variables
+pub fn parse_args(args: &[String]) -> (Vec<String>, Vec<Variable>) {
     let mut variables = Vec::new();
-    let mut task_name = String::new();
+    let mut words = Vec::new();
 
-    for arg in args.iter().skip(1) {
+    for arg in args {
         if let Some(start) = arg.find("[:") {
             if let Some(end) = arg[start..].find(']') {
                 let var_str = &arg[start + 2..start + end];
@@ -422,12 +496,13 @@ fn parse_args(args: &[String]) -> (String, Vec<Variable>) {
                     variables.push(Variable::new(name, content));
                 }
             }
-        } else if task_name.is_empty() { // Assuming the first non-variable argument is the task name
-            task_name = arg.to_lowercase(); // Keeping the task name lowercase for consistency
+        } else {
+            // words keep their case, task names and signature words are matched case insensitively
+            words.push(arg.clone());
         }
     }
 
-    (task_name, variables)
+    (words, variables)
 }
 
 
@@ -454,6 +529,20 @@ pub fn print_patching_variable(name: &str, value: &Atom) {
     showln!(cyan_bold, "• ", gray_dim, name, cyan_bold, " » ", white, value);
 }
 
+/// renders a parse error the way compilers do, headline, location and the offending line with a caret under it
+pub fn show_parse_error(error: &ParseError) {
+    showln!(red_bold, "error", white_bold, ": ", white_bold, error.message());
+    showln!(gray_dim, " --> ", yellow_bold, error.location());
+    let lines = error.snippet.lines().collect::<Vec<_>>();
+    for (i, line) in lines.iter().enumerate() {
+        if i + 1 == lines.len() {
+            showln!(red_bold, line);
+        } else {
+            showln!(gray_dim, line);
+        }
+    }
+}
+
 pub fn show_error(line: &str) {
     let mut remaining_line = line.to_string();
     while remaining_line.chars().count() > 56 {
@@ -507,6 +596,24 @@ pub fn format_elapsed_time(elapsed: std::time::Duration) -> String {
     }
 }
 
+/// footer of a run that failed, e.g `╰─ exited with status 2 ───── 3ms` in red
+pub fn print_failure(elapsed: String, error: &MotoError) {
+    let message = format!(" {} ", error);
+    let len = 60usize.saturating_sub(elapsed.len() + message.chars().count() + 3);
+    showln!(
+        red_bold,
+        "╰─",
+        red_bold,
+        message,
+        red_bold,
+        "─".repeat(len),
+        gray,
+        " ",
+        yellow_bold,
+        elapsed
+    );
+}
+
 pub fn print_elapsed_time(elapsed: String) {
     let len = 60 - elapsed.len() - 3;
     showln!(
@@ -0,0 +1,200 @@
+use super::*;
+
+#[derive(Debug, Clone, PartialEq)]
+///a cell found by a lookup along with the packages it is defined in, outermost first.
+/// e.g `task install` of `package rust` in `im.moto` is found with the namespace `["im", "rust"]`
+pub struct Resolved<T> {
+    pub namespace: Vec<String>,
+    pub cell: T,
+}
+
+impl<T> Resolved<T> {
+    /// the name that reaches `member` of this namespace from anywhere, e.g `im:rust:install`
+    pub fn qualify(&self, member: &str) -> String {
+        qualify(&self.namespace, member)
+    }
+}
+
+/// `member` of `namespace` as one name, e.g `im:rust:install`
+pub fn qualify(namespace: &[String], member: &str) -> String {
+    namespace
+        .iter()
+        .map(String::as_str)
+        .chain(std::iter::once(member))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// splits `im:rust:install` into the qualifier `["im", "rust"]` and the member `install`
+pub fn split_qualified(name: &str) -> (Vec<String>, String) {
+    let mut parts = name.split(':').map(|part| part.trim().to_string()).collect::<Vec<_>>();
+    let member = parts.pop().unwrap_or_default();
+    (parts, member)
+}
+
+/// the cells of the package at `path`, the root cells for an empty path
+fn cells_at<'a>(cells: &'a [Cell], path: &[String]) -> Option<&'a [Cell]> {
+    let Some((first, rest)) = path.split_first() else {
+        return Some(cells);
+    };
+    cells.iter().find_map(|cell| match cell {
+        Cell::Package(package) if package.identifier_is(first) => cells_at(&package.children, rest),
+        _ => None,
+    })
+}
+
+/// every package at any depth whose namespace ends with `qualifier` and that defines `member`
+fn collect(
+    cells: &[Cell],
+    namespace: &mut Vec<String>,
+    qualifier: &[String],
+    member: &str,
+    is: fn(&Cell, &str) -> bool,
+    found: &mut Vec<Resolved<Cell>>,
+) {
+    for cell in cells {
+        if let Cell::Package(package) = cell {
+            namespace.push(package.name());
+            if namespace.ends_with(qualifier) {
+                if let Some(cell) = package.children.iter().find(|cell| is(cell, member)) {
+                    found.push(Resolved { namespace: namespace.clone(), cell: cell.clone() });
+                }
+            }
+            collect(&package.children, namespace, qualifier, member, is, found);
+            namespace.pop();
+        }
+    }
+}
+
+fn is_task(cell: &Cell, name: &str) -> bool {
+    matches!(cell, Cell::Task(task) if task.identifier_is(name))
+}
+
+fn is_runtime(cell: &Cell, name: &str) -> bool {
+    matches!(cell, Cell::Runtime(runtime) if runtime.identifier_is(name))
+}
+
+fn is_value(cell: &Cell, name: &str) -> bool {
+    matches!(cell, Cell::Assignment(_) | Cell::Block(_) if cell.identifier_is(name))
+}
+
+impl Ctx {
+    /// a child scope for running code defined in `namespace`, unqualified names resolve there first
+    pub fn within(&self, namespace: Vec<String>) -> Ctx {
+        Ctx { namespace, ..self.child() }
+    }
+
+    /// finds the cell a possibly qualified name like `install`, `rust:install` or `r:rust` refers to.
+    /// the package the scope runs in is searched first, then the packages enclosing it up to the root.
+    /// after that every package whose namespace ends with the qualifier is a candidate,
+    /// more than one of them is an error asking to qualify the name
+    async fn resolve(&self, name: &str, is: fn(&Cell, &str) -> bool) -> Result<Option<Resolved<Cell>>, MotoError> {
+        let (qualifier, member) = split_qualified(name);
+        let children = self.children.lock().await;
+
+        for depth in (0..=self.namespace.len()).rev() {
+            let mut path = self.namespace[..depth].to_vec();
+            path.extend(qualifier.iter().cloned());
+            if let Some(cell) = cells_at(&children, &path).and_then(|cells| cells.iter().find(|cell| is(cell, &member))) {
+                return Ok(Some(Resolved { namespace: path, cell: cell.clone() }));
+            }
+        }
+
+        let mut found = vec![];
+        collect(&children, &mut vec![], &qualifier, &member, is, &mut found);
+        if found.len() > 1 {
+            return Err(MotoError::Ambiguous {
+                name: name.to_string(),
+                candidates: found.iter().map(|resolved| resolved.qualify(&member)).collect(),
+            });
+        }
+        Ok(found.pop())
+    }
+
+    /// the task a call like `[:install()]` or `[:rust:install()]` refers to
+    pub async fn get_task(&self, name: impl Into<String>) -> Result<Resolved<Task>, MotoError> {
+        let name = name.into();
+        match self.resolve(&name, is_task).await? {
+            Some(Resolved { namespace, cell: Cell::Task(task) }) => Ok(Resolved { namespace, cell: task }),
+            _ => Err(MotoError::TaskNotFound { name, scope: None }),
+        }
+    }
+
+    /// the `runtime` cell a tail like `:rust` or `:r:rust` refers to
+    pub async fn get_runtime(&self, name: impl Into<String>) -> Result<Resolved<Runtime>, MotoError> {
+        let name = name.into();
+        match self.resolve(&name, is_runtime).await? {
+            Some(Resolved { namespace, cell: Cell::Runtime(runtime) }) => Ok(Resolved { namespace, cell: runtime }),
+            _ => Err(MotoError::RuntimeNotFound { name, used_by: None }),
+        }
+    }
+
+    /// the `let` or block that `[:name]` refers to, found without computing its value
+    pub async fn find_value(&self, name: &str) -> Result<Option<Resolved<Cell>>, MotoError> {
+        let name = name.split('.').next().unwrap_or_default();
+        self.resolve(name, is_value).await
+    }
+
+    /// whether a `let` or a block named like `[:name]` can be reached from this scope
+    pub async fn defines_value(&self, name: &str) -> Result<bool, MotoError> {
+        Ok(self.find_value(name).await?.is_some())
+    }
+
+    /// the value of `[:name]`. variables set in scope, those of the command line included, come first,
+    /// then the ones of the environment, see [`Environment`], then `let` assignments and
+    /// blocks of the packages, e.g `[:rust:version]` or `[:credits]`.
+    /// `let` values are evaluated and blocks interpolated in their own package,
+    /// a path like `[:config.server.port]` reaches into the value. `[:env.HOME]` is the environment variable `HOME`
+    pub async fn resolve_variable(&self, name: impl Into<String>) -> Result<Option<Atom>, MotoError> {
+        let name = name.into();
+        let mut path = name.trim().split('.');
+        let name = path.next().unwrap_or_default();
+        let path = path.collect::<Vec<_>>();
+
+        if let ("env", [variable]) = (name, path.as_slice()) {
+            return Ok(self.environment.lock().await.get(variable));
+        }
+        let given = match name.contains(':') {
+            true => None,
+            false => match self.get_variable(name).await {
+                Some(value) => Some(value),
+                None => self.environment.lock().await.variables.get(&name.to_lowercase()).map(|setting| setting.value.clone()),
+            },
+        };
+        let value = match given {
+            Some(value) => Some(value),
+            None => match self.resolve(name, is_value).await? {
+                Some(Resolved { namespace, cell: Cell::Assignment(assignment) }) => {
+                    Some(self.within(namespace).evaluate_assignment(&assignment).await?)
+                }
+                Some(Resolved { namespace, cell: Cell::Block(block) }) => {
+                    let body = Box::pin(dope(&self.within(namespace), block.body.clone())).await?;
+                    let value = block.value(&body).map_err(|reason| MotoError::InvalidBlock {
+                        name: block.name(),
+                        runtime: block.runtime(),
+                        defined_at: block.span.to_string(),
+                        reason,
+                    })?;
+                    Some(value)
+                }
+                _ => None,
+            },
+        };
+        Ok(value.and_then(|value| value.get_path(&path)))
+    }
+}
+
+/// the tasks of `cells` and of the packages among them at any depth, with their namespaces
+pub fn namespaced_tasks(cells: &[Cell], namespace: &mut Vec<String>, tasks: &mut Vec<Resolved<Task>>) {
+    for cell in cells {
+        match cell {
+            Cell::Task(task) => tasks.push(Resolved { namespace: namespace.clone(), cell: task.clone() }),
+            Cell::Package(package) => {
+                namespace.push(package.name());
+                namespaced_tasks(&package.children, namespace, tasks);
+                namespace.pop();
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,22 +1,282 @@
 use super::*;
 use nom::{
     branch::alt,
-    bytes::complete::{is_a, is_not, tag, take_till, take_until, take_while1},
-    character::complete::{
-        alphanumeric1, char, digit1, multispace0, multispace1, none_of, one_of,
-    },
-    combinator::{eof, map, not, opt, peek, recognize, rest},
-    multi::{fold_many0, many0, many1, many_till, separated_list0},
-    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
-    FindSubstring, IResult, InputTake,
+    bytes::complete::{escaped, is_not, tag, take_till, take_until, take_while, take_while1},
+    character::complete::{anychar, char, digit1, hex_digit1, multispace0, multispace1, none_of, one_of},
+    combinator::{eof, map, map_opt, opt, peek, recognize, rest},
+    error::{context, ContextError, ErrorKind, ParseError as _},
+    multi::{count, many0, many1, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    Err, IResult,
 };
+use std::path::Path;
+
+/// result of every parser in this module
+pub type PResult<'a, O> = IResult<&'a str, O, SyntaxError<'a>>;
+
+/// the nom error used while parsing
+/// it remembers where parsing gave up and which tokens would have been accepted there.
+/// when two alternatives fail, the one that got further into the input wins
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError<'a> {
+    pub input: &'a str,
+    pub expected: Vec<String>,
+}
+
+impl<'a> nom::error::ParseError<&'a str> for SyntaxError<'a> {
+    fn from_error_kind(input: &'a str, _kind: ErrorKind) -> Self {
+        SyntaxError { input, expected: vec![] }
+    }
+
+    fn append(_input: &'a str, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn from_char(input: &'a str, c: char) -> Self {
+        SyntaxError { input, expected: vec![format!("`{}`", c)] }
+    }
+
+    fn or(mut self, other: Self) -> Self {
+        match self.input.len().cmp(&other.input.len()) {
+            std::cmp::Ordering::Less => self,
+            std::cmp::Ordering::Greater => other,
+            std::cmp::Ordering::Equal => {
+                for expected in other.expected {
+                    if !self.expected.contains(&expected) {
+                        self.expected.push(expected);
+                    }
+                }
+                self
+            }
+        }
+    }
+}
+
+impl<'a> ContextError<&'a str> for SyntaxError<'a> {
+    fn add_context(input: &'a str, ctx: &'static str, mut other: Self) -> Self {
+        // only name the token when nothing inside it matched, otherwise the deeper error is more precise
+        if other.input.len() == input.len() {
+            other.expected = vec![ctx.to_string()];
+        }
+        other
+    }
+}
+
+/// a parse failure located in the source it came from
+/// e.g
+/// ```text
+/// expected `}:`, found `task`
+///  --> im.moto:42:5
+///    |
+/// 42 |     task build {
+///    |     ^^^^
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub expected: Vec<String>,
+    pub found: String,
+    pub snippet: String,
+}
+
+impl ParseError {
+    /// locates `error` inside `source`, which must be the text the failing parser was given
+    pub fn new(source: &str, error: SyntaxError) -> Self {
+        let offset = source.len().saturating_sub(error.input.len());
+        let (line, column) = Span::line_column(source, offset);
+        let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_text = source[line_start..].lines().next().unwrap_or_default();
+
+        let found = error
+            .input
+            .split_whitespace()
+            .next()
+            .map(|token| token.chars().take(24).collect::<String>())
+            .unwrap_or_default();
+
+        let gutter = " ".repeat(line.to_string().len());
+        let carets = "^".repeat(found.chars().count().max(1));
+        let snippet = format!(
+            "{gutter} |\n{line} | {line_text}\n{gutter} | {}{carets}",
+            " ".repeat(column - 1)
+        );
+
+        ParseError {
+            file: None,
+            line,
+            column,
+            expected: error.expected,
+            found,
+            snippet,
+        }
+    }
+
+    pub fn from_nom(source: &str, error: Err<SyntaxError>) -> Self {
+        match error {
+            Err::Error(e) | Err::Failure(e) => Self::new(source, e),
+            Err::Incomplete(_) => Self::new(source, SyntaxError { input: "", expected: vec![] }),
+        }
+    }
+
+    pub fn in_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.file = Some(path.as_ref().display().to_string());
+        self
+    }
+
+    /// the `expected ..., found ...` headline of the error
+    pub fn message(&self) -> String {
+        let found = if self.found.is_empty() {
+            "end of file".to_string()
+        } else {
+            format!("`{}`", self.found)
+        };
+        match self.expected.len() {
+            0 => format!("unexpected {}", found),
+            1 => format!("expected {}, found {}", self.expected[0], found),
+            _ => format!("expected one of {}, found {}", self.expected.join(", "), found),
+        }
+    }
+
+    /// `file:line:column` of the error
+    pub fn location(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            self.file.as_deref().unwrap_or("<input>"),
+            self.line,
+            self.column
+        )
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.message())?;
+        writeln!(f, " --> {}", self.location())?;
+        write!(f, "{}", self.snippet)
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 ///parse script
-pub fn parse(input: &str) -> Result<Vec<Cell>, String> {
-    match parse_cells(input) {
-        Ok((_, cells)) => Ok(cells),
-        Err(e) => Err(format!("{:?}", e)),
+/// the whole input has to be made of cells, anything left over is reported as an error
+pub fn parse(input: &str) -> Result<Vec<Cell>, ParseError> {
+    let (rest, mut cells) = parse_cells(input).map_err(|e| ParseError::from_nom(input, e))?;
+    if rest.is_empty() {
+        cells.iter_mut().for_each(|cell| cell.locate(input, None));
+        return Ok(cells);
     }
+    // re-run the cell parser on what is left so the error points at the deepest failure
+    match parse_cell(rest) {
+        Err(e) => Err(ParseError::from_nom(input, e)),
+        Ok(_) => Err(ParseError::new(input, SyntaxError { input: rest, expected: vec![] })),
+    }
+}
+
+///parse a script read from `path`, errors carry the path
+pub fn parse_file(path: impl AsRef<Path>, input: &str) -> Result<Vec<Cell>, ParseError> {
+    let file = path.as_ref().display().to_string();
+    let mut cells = parse(input).map_err(|e| e.in_file(&file))?;
+    cells.iter_mut().for_each(|cell| cell.locate(input, Some(&file)));
+    Ok(cells)
+}
+
+#[test]
+fn test_parse_spans() {
+    let input = "let x = 5;\n\n// greets\ntask greet {\n    echo hi\n}:shell\n\nruntime dart {\n    task run {\n        dart run\n    }:shell\n}:moto\n";
+    let cells = parse_file("im.moto", input).unwrap();
+
+    assert_eq!(cells[0].span().to_string(), "im.moto:1");
+    let task = cells[1].span();
+    assert_eq!((task.line, task.column), (4, 1));
+    assert_eq!(&input[task.start..task.end], "task greet {\n    echo hi\n}:shell");
+
+    let Cell::Runtime(runtime) = &cells[2] else { panic!("expected a runtime") };
+    assert_eq!(runtime.span.to_string(), "im.moto:8");
+    assert_eq!(runtime.children[0].span().to_string(), "im.moto:9");
+    assert_eq!((runtime.children[0].span().column), 5);
+}
+
+#[test]
+fn test_syntax_tree_round_trip() {
+    let input = "\n// tools\nlet x =  5 ; // five\npackage p { /* inner */\n  import math as m;\n\n\n  [:greet()];\n}:moto \n";
+    let tree = SyntaxTree::parse("im.moto", input).unwrap();
+    assert_eq!(tree.to_string(), input);
+    let SyntaxNode::Cell(package) = &tree.nodes[7] else { panic!("expected a cell") };
+    let CellSyntax::Container { open, children, close } = &package.syntax else { panic!("expected a container") };
+    assert_eq!((open.as_str(), close.as_str()), ("package p {", "}:moto"));
+    assert!(children.contains(&SyntaxNode::Comment("/* inner */".into())));
+}
+
+#[test]
+fn test_format() {
+    let input = "\n\n// tools\nlet   x =  5 ; // five\ntask greet {echo hi}:shell\n\n\n\npackage p { // inner\n  runtime r   {\n      task run {\n            echo a\n              echo b\n      }:shell\n  }:moto\n  import \"math\" as m\n}:moto \n\n";
+    let formatted = format_source("im.moto", input).unwrap();
+    assert_eq!(
+        formatted,
+        "// tools\nlet x = 5; // five\ntask greet { echo hi }:shell\n\npackage p { // inner\n    runtime r {\n        task run {\n            echo a\n              echo b\n        }:shell\n    }:moto\n    import math as m;\n}:moto\n"
+    );
+    assert_eq!(format_source("im.moto", &formatted).unwrap(), formatted);
+
+    // re-indenting would change the text of the string
+    let input = "task note {\n  echo \"a\nb\"\n}:shell\n";
+    assert_eq!(format_source("im.moto", input).unwrap(), input);
+
+    // only spaces and tabs are indentation
+    let input = "task wide {\n  echo a\n\u{3000}echo b\n}:shell\n";
+    assert_eq!(format_source("im.moto", input).unwrap(), "task wide {\n      echo a\n    \u{3000}echo b\n}:shell\n");
+}
+
+#[test]
+fn test_parse_body_variables() {
+    let body = "echo [:name] [:who=(mom | dad)] [:port::number=80] [:dir=C:\\tools] [:rust:version] [:build()] [:name] [:config:file]";
+    let variables = parse_body_variables(body);
+    let names = variables.iter().map(Variable::name).collect::<Vec<_>>();
+    assert_eq!(names, ["name", "who", "port", "dir", "rust:version", "name", "config:file"]);
+    assert_eq!(variables[2].constraint, Some(VariableConstraint::Number));
+    assert_eq!(variables[6].constraint, None);
+    assert_eq!(variables[3].value, Atom::from("C:\\tools"));
+    let task = Task::new("t", body, "shell");
+    assert_eq!(task.variables(), ["name", "who", "port", "dir"]);
+}
+
+#[test]
+fn test_serde_round_trip() {
+    let input = "let x = [1, \"a\", {b: -[:y=2]}];\npackage p {\n    task (please)? open [:path=\"chrome\"] { start [:path] }:shell\n    block config { {} }:json\n    import math as m;\n    [:open(path: \"x\")]\n}:moto\n";
+    let cells = parse_file("im.moto", input).unwrap();
+    let json = serde_json::to_value(&cells).unwrap();
+    assert_eq!(json[0]["kind"], "assignment");
+    assert_eq!(json[1]["children"][0]["identifier"], "open");
+    assert_eq!(json[1]["children"][0]["span"]["line"], 3);
+    assert_eq!(serde_json::from_value::<Vec<Cell>>(json).unwrap(), cells);
+}
+
+#[test]
+fn test_parse_error_location() {
+    let input = "task greet {\n    echo hi\n}:shell\n\ntask {\n}:shell\n";
+    let error = parse_file("im.moto", input).unwrap_err();
+    assert_eq!(error.location(), "im.moto:5:6");
+    assert_eq!(error.expected, vec!["identifier".to_string()]);
+    assert_eq!(error.found, "{");
+    assert!(error.snippet.ends_with("5 | task {\n  |      ^"));
+}
+
+#[test]
+fn test_parse_error_reports_deepest_failure() {
+    let input = r#"
+        package rust {
+            let version = "1.0.0"
+            task build {
+                cargo build
+            }:shell
+        }:moto
+        "#;
+    let error = ParseError::from_nom(input, parse_package(input).unwrap_err());
+    assert_eq!(error.line, 4);
+    assert_eq!(error.expected, vec!["`;`".to_string()]);
+    assert_eq!(error.found, "task");
 }
 
 #[test]
@@ -54,7 +314,7 @@ fn test_parse_cells() {
 ///parse cells
 /// a cell can be a task, a runtime, a block, a variable etc
 /// cells can be nested and can contain other cells
-pub fn parse_cell(input: &str) -> IResult<&str, Cell> {
+pub fn parse_cell(input: &str) -> PResult<'_, Cell> {
 
     let (input, cell) = delimited(
         ignore_comments_and_spaces,
@@ -64,6 +324,8 @@ pub fn parse_cell(input: &str) -> IResult<&str, Cell> {
             map(parse_runtime, Cell::Runtime),
             map(parse_block, Cell::Block),
             map(parse_import, Cell::Import),
+            map(parse_package, Cell::Package),
+            map(parse_invocation, Cell::Invocation),
         )),
         ignore_comments_and_spaces,
     )(input)?;
@@ -75,10 +337,50 @@ pub fn parse_cell(input: &str) -> IResult<&str, Cell> {
 
 
 
-pub fn parse_cells(input: &str) -> IResult<&str, Vec<Cell>> {
+pub fn parse_cells(input: &str) -> PResult<'_, Vec<Cell>> {
     many0(parse_cell)(input)
 }
 
+/// parses child cells with `cell` until the `}:` that closes the parent.
+/// when the closing tag is not there, the error of the child that got furthest is reported instead
+/// since a broken child is almost always the actual mistake
+pub fn parse_children<'a>(
+    mut input: &'a str,
+    mut cell: impl FnMut(&'a str) -> PResult<'a, Cell>,
+) -> PResult<'a, Vec<Cell>> {
+    let mut children = vec![];
+    let mut child_error = None;
+    loop {
+        match cell(input) {
+            Ok((rest, child)) if rest.len() < input.len() => {
+                children.push(child);
+                input = rest;
+            }
+            Ok(_) => break,
+            Err(Err::Error(e)) => {
+                child_error = Some(e);
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let (input, _) = ignore_comments_and_spaces(input)?;
+    match peek(closing_tag)(input) {
+        Ok(_) => Ok((input, children)),
+        Err(Err::Error(e)) => Err(Err::Error(match child_error {
+            Some(child_error) => child_error.or(e),
+            None => e,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// the `}:` that ends a cell and introduces its tail
+pub fn closing_tag(input: &str) -> PResult<'_, &str> {
+    context("`}:`", tag("}:"))(input)
+}
+
 #[test]
 fn test_parse_package() {
     let input = r#"
@@ -98,9 +400,9 @@ fn test_parse_package() {
         }:moto
         "#;
 
-    let (input, result) = parse_package(input).unwrap();
+    let (_, result) = parse_package(input).unwrap();
     assert_eq!(
-        result,
+        result.without_spans(),
         Package {
             identifer: Identifier::new("rust"),
             children: vec![
@@ -112,14 +414,18 @@ fn test_parse_package() {
                         Cell::Assignment(Assignment::new("path", "path/to/rust.exe")),
                         Cell::Task(Task {
                             identifer: Identifier::new("build"),
-                            body: String::from("echo \"Building with rust\" [:path] --version [:path] run [:file]"),
-                            runtime: Identifier::new("shell")
+                            signature: TaskSignature::default(),
+                            body: String::from("\n                    echo \"Building with rust\"\n                    [:path] --version\n                    [:path] run [:file]\n                "),
+                            runtime: Identifier::new("shell"),
+                            span: Span::default(),
                         })
                     ],
-                    runtime: "moto".into()
+                    runtime: "moto".into(),
+                    span: Span::default(),
                 })
             ],
-            runtime: "moto".into()
+            runtime: "moto".into(),
+            span: Span::default(),
         }
     );
 }
@@ -198,11 +504,11 @@ task greet_from_dart {
 // comments can appear anywhere in the script and are ignored by the parser
 // comments can also appear at the end of a line and are ignored by the parser
 // linebreaks after a comment are also ignored by the parser until a non comment character is encountered
-pub fn comment(input: &str) -> IResult<&str, &str> {
+pub fn comment(input: &str) -> PResult<'_, &str> {
     alt((single_line_comment, multi_line_comment))(input)
 }
 
-pub fn single_line_comment(input: &str) -> IResult<&str, &str> {
+pub fn single_line_comment(input: &str) -> PResult<'_, &str> {
 
     let (input, _) = tag("//")(input)?;
     let (input, _) = take_till(|c| c == '\n')(input)?;
@@ -211,7 +517,7 @@ pub fn single_line_comment(input: &str) -> IResult<&str, &str> {
     Ok((input, ""))
 }
 
-pub fn multi_line_comment(input: &str) -> IResult<&str, &str> {
+pub fn multi_line_comment(input: &str) -> PResult<'_, &str> {
     let (input, _) = tag("/*")(input)?;
     let (input, _) = take_until("*/")(input)?;
     let (input, _) = tag("*/")(input)?;
@@ -219,7 +525,7 @@ pub fn multi_line_comment(input: &str) -> IResult<&str, &str> {
     Ok((input, ""))
 }
 
-pub fn comments(input: &str) -> IResult<&str, &str> {
+pub fn comments(input: &str) -> PResult<'_, &str> {
     let (input, _) = multispace0(input)?;
     let (input, _) = many0(comment)(input)?;
     let (input, _) = multispace0(input)?;
@@ -227,17 +533,18 @@ pub fn comments(input: &str) -> IResult<&str, &str> {
 }
 
 // captures and ignores optional comments and spaces until end of comment or space
-pub fn ignore_comments_and_spaces(input: &str) -> IResult<&str, &str> {
+pub fn ignore_comments_and_spaces(input: &str) -> PResult<'_, &str> {
     let (input, _) = multispace0(input)?;
     let (input, _) = opt(comments)(input)?;
     let (input, _) = multispace0(input)?;
     Ok((input, ""))
 }
 
-pub fn parse_package(input: &str) -> IResult<&str, Package> {
+pub fn parse_package(input: &str) -> PResult<'_, Package> {
 
         let (input, _) = ignore_comments_and_spaces(input)?;
-        let (input, _) = tag("package")(input)?;
+        let start = input;
+        let (input, _) = context("`package`", tag("package"))(input)?;
         let (input, _) = multispace1(input)?;
         let (input, identifier) = parse_identifier(input)?;
         let (input, _) = ignore_comments_and_spaces(input)?;
@@ -245,18 +552,20 @@ pub fn parse_package(input: &str) -> IResult<&str, Package> {
         let (input, _) = ignore_comments_and_spaces(input)?;
     
         // Parse children (assignments or tasks) until the closing tag of the runtime block
-        let (input, children) = many0(alt((
+        let (input, children) = parse_children(input, alt((
             map(parse_assignment, Cell::Assignment),
             map(parse_task, Cell::Task),
             map(parse_runtime, Cell::Runtime),
             map(parse_block, Cell::Block),
             map(parse_import, Cell::Import),
-
-        )))(input)?;
+            map(parse_package, Cell::Package),
+            map(parse_invocation, Cell::Invocation),
+        )))?;
     
         let (input, _) = ignore_comments_and_spaces(input)?;
-        let (input, _) = tag("}:")(input)?;
-        let (input, runtime) = parse_identifier(input)?;
+        let (input, _) = closing_tag(input)?;
+        let (input, runtime) = parse_qualified_identifier(input)?;
+        let span = Span::between(start, input);
         let (input, _) = ignore_comments_and_spaces(input)?;
         let (input, _) = opt(eof)(input)?; // Optional EOF to ensure parsing until the end of input
     
@@ -266,6 +575,7 @@ pub fn parse_package(input: &str) -> IResult<&str, Package> {
                 identifer: identifier,
                 children,
                 runtime,
+                span,
             },
         ))
     
@@ -282,35 +592,39 @@ fn test_parse_assignment() {
 
     let (input, result) = parse_assignment(input).unwrap();
     assert_eq!(
-        result,
+        result.without_spans(),
         Assignment {
             identifier: Identifier ("x".to_string()),
-            value: Atom::Number(5.0)
+            value: Atom::Number(5.0),
+            span: Span::default(),
         }
     );
 
     let (input, result) = parse_assignment(input).unwrap();
     assert_eq!(
-        result,
+        result.without_spans(),
         Assignment {
             identifier: Identifier ("y".to_string()),
-            value: Atom::String("hello".to_string())
+            value: Atom::String("hello".to_string()),
+            span: Span::default(),
         }
     );
 
-    let (input, result) = parse_assignment(input).unwrap();
+    let (_, result) = parse_assignment(input).unwrap();
     assert_eq!(
-        result,
+        result.without_spans(),
         Assignment {
             identifier: Identifier::new("z"),
-            value: Atom::Boolean(true)
+            value: Atom::Boolean(true),
+            span: Span::default(),
         }
     );
 }
 
-pub fn parse_assignment(input: &str) -> IResult<&str, Assignment> {
+pub fn parse_assignment(input: &str) -> PResult<'_, Assignment> {
     let (input, _) = ignore_comments_and_spaces(input)?;
-    let (input, _) = tag("let")(input)?;
+    let start = input;
+    let (input, _) = context("`let`", tag("let"))(input)?;
     let (input, _) = multispace1(input)?;
     let (input, identifer) = parse_identifier(input)?;
     let (input, _) = ignore_comments_and_spaces(input)?;
@@ -324,6 +638,7 @@ pub fn parse_assignment(input: &str) -> IResult<&str, Assignment> {
         Assignment {
             identifier: identifer,
             value,
+            span: Span::between(start, input),
         },
     ))
 }
@@ -334,13 +649,15 @@ fn test_parse_task() {
         task greet {echo "hello world"}:shell
         "#;
 
-    let (input, result) = parse_task(input).unwrap();
+    let (_, result) = parse_task(input).unwrap();
     assert_eq!(
-        result,
+        result.without_spans(),
         Task {
             identifer: Identifier::new("greet"),
+            signature: TaskSignature::default(),
             body: String::from("echo \"hello world\""),
-            runtime: Identifier::new("shell")
+            runtime: Identifier::new("shell"),
+            span: Span::default(),
         }
     );
 
@@ -348,40 +665,262 @@ fn test_parse_task() {
             print("hello ") [:name]
         }:dart"#;
 
-    let (input, result) = parse_task(input).unwrap();
+    let (_, result) = parse_task(input).unwrap();
 
     assert_eq!(
-        result,
+        result.without_spans(),
         Task {
             identifer: Identifier::new("greet"),
-            body:   String::from("print(\"hello \") [:name]"),
-            runtime: Identifier::new("dart")
+            signature: TaskSignature::default(),
+            body:   String::from("\n            print(\"hello \") [:name]\n        "),
+            runtime: Identifier::new("dart"),
+            span: Span::default(),
         }
     );
 }
 
-pub fn parse_task(input: &str) -> IResult<&str, Task> {
+pub fn parse_task(input: &str) -> PResult<'_, Task> {
     let (input, _) = ignore_comments_and_spaces(input)?;
-    let (input, _) = tag("task")(input)?;
+    let start = input;
+    let (input, _) = context("`task`", tag("task"))(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, identifier) = parse_identifier(input)?;
+    let (input, (identifier, signature)) = parse_task_header(input)?;
     let (input, _) = ignore_comments_and_spaces(input)?;
-    let (input, _) = tag("{")(input)?;
-    let (input, body) = take_until("}:")(input)?;
+    let (input, body) = parse_body(input)?;
     // Ensure that the closing tag is consumed by the task parser
-    let (input, _) = tag("}:")(input)?;
-    let (input, runtime) = parse_identifier(input)?;
+    let (input, _) = closing_tag(input)?;
+    let (input, runtime) = parse_qualified_identifier(input)?;
 
     Ok((
         input,
         Task {
             identifer: identifier,
+            signature,
             body: String::from(body),
             runtime,
+            span: Span::between(start, input),
         },
     ))
 }
 
+#[test]
+fn test_parse_task_header() {
+    let (input, (identifier, signature)) = parse_task_header("greet {").unwrap();
+    assert_eq!(input, "{");
+    assert_eq!(identifier, Identifier::new("greet"));
+    assert!(signature.is_empty());
+
+    let (input, (identifier, signature)) =
+        parse_task_header("(please)? open (browser | [:path=\"chrome.exe\"]) {").unwrap();
+    assert_eq!(input, "{");
+    assert_eq!(identifier, Identifier::new("open"));
+    assert_eq!(
+        signature,
+        TaskSignature::new(vec![
+            SignaturePart::Group {
+                alternatives: vec![vec![SignaturePart::Word("please".into())]],
+                optional: true,
+            },
+            SignaturePart::Word("open".into()),
+            SignaturePart::Group {
+                alternatives: vec![
+                    vec![SignaturePart::Word("browser".into())],
+                    vec![SignaturePart::Parameter(Variable::new("path", "chrome.exe"))],
+                ],
+                optional: false,
+            },
+        ])
+    );
+    assert_eq!(signature.to_string(), "(please)? open (browser | [:path=\"chrome.exe\"])");
+
+    let words = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<_>>();
+    let task = Task { signature, ..Task::new(identifier, "", "shell") };
+    assert_eq!(task.matches(&words("open browser")), Some(vec![]));
+    assert_eq!(
+        task.matches(&words("please open firefox.exe")),
+        Some(vec![("path".into(), "firefox.exe".into())])
+    );
+    assert_eq!(task.matches(&words("open")), Some(vec![]));
+    assert_eq!(task.matches(&words("please")), None);
+
+    let (_, (identifier, signature)) = parse_task_header("go (to)? [:path] [:args] {").unwrap();
+    assert_eq!(identifier, Identifier::new("go"));
+    let task = Task { signature, ..Task::new(identifier, "", "shell") };
+    assert_eq!(
+        task.matches(&words("go to home -a -b")),
+        Some(vec![("path".into(), "home".into()), ("args".into(), "-a -b".into())])
+    );
+    assert_eq!(task.matches(&words("go")), None);
+
+    assert!(parse_task_header("(please)? {").is_err());
+}
+
+/// parses what comes between `task` and the body: a bare name, or a signature made of words,
+/// `[:parameters]` and `(alternative | groups)` that can be made optional with a trailing `?`.
+/// the first word outside of a group names the task
+pub fn parse_task_header(input: &str) -> PResult<'_, (Identifier, TaskSignature)> {
+    let (rest, parts) = many0(parse_signature_part)(input)?;
+    let identifier = parts.iter().find_map(|part| match part {
+        SignaturePart::Word(word) => Some(Identifier::new(word.clone())),
+        _ => None,
+    });
+    let Some(identifier) = identifier else {
+        let error = SyntaxError::from_error_kind(input, ErrorKind::Alpha);
+        return Err(Err::Error(SyntaxError::add_context(input, "identifier", error)));
+    };
+
+    let signature = match parts.as_slice() {
+        [SignaturePart::Word(_)] => TaskSignature::default(),
+        _ => TaskSignature::new(parts),
+    };
+    Ok((rest, (identifier, signature)))
+}
+
+pub fn parse_signature_part(input: &str) -> PResult<'_, SignaturePart> {
+    let (input, _) = multispace0(input)?;
+    let (input, part) = alt((
+        map(parse_variable, SignaturePart::Parameter),
+        parse_signature_group,
+        map(parse_identifier, |identifier| SignaturePart::Word(identifier.0)),
+    ))(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, part))
+}
+
+pub fn parse_signature_group(input: &str) -> PResult<'_, SignaturePart> {
+    let (input, _) = char('(')(input)?;
+    let (input, alternatives) = separated_list1(char('|'), many1(parse_signature_part))(input)?;
+    let (input, _) = context("`)`", char(')'))(input)?;
+    let (input, optional) = opt(char('?'))(input)?;
+    Ok((
+        input,
+        SignaturePart::Group {
+            alternatives,
+            optional: optional.is_some(),
+        },
+    ))
+}
+
+#[test]
+fn test_parse_body() {
+    let bodies = [
+        // python set literal followed by a colon
+        "for k in {\"a\": 1}:\n    print(k)",
+        // js object literal followed by a label
+        "const o = {a: {b: 1}}\nouter: {\n  break outer;\n}",
+        // powershell hashtable and here-string
+        "$h = @{ a = 1 }\n$code = @'\n}:ps\n'@",
+        // strings and heredocs
+        "echo \"}:\" '}:'\ncat <<EOF\n}:shell\nEOF\n",
+        "x = \"\"\"\n}:\n\"\"\"",
+        "don't { stop }",
+        // backticks are no quotes, in powershell they escape inside double quotes
+        "Write-Host \"`\"}:\" `\n    -NoNewline",
+        // multi-byte characters, in and out of strings
+        "echo héllo wörld 'ünïcode' {😀}",
+    ];
+    for body in bodies {
+        let input = format!("{{{}}}:shell trailing", body);
+        let (rest, parsed) = parse_body(&input).unwrap();
+        assert_eq!(parsed, body);
+        assert_eq!(rest, "}:shell trailing");
+    }
+
+    let input = "task greet {\n    match x { 1 => { \"}:\" }, _ => {} }\n}:rust\ntask other { echo }:shell";
+    let (_, cells) = parse_cells(input).unwrap();
+    assert_eq!(cells.len(), 2);
+    assert_eq!(cells[0].get_body().unwrap().trim(), "match x { 1 => { \"}:\" }, _ => {} }");
+    let (_, cells) = parse_cells("task greet { echo héllo }:shell").unwrap();
+    assert_eq!(cells[0].get_body().unwrap().trim(), "echo héllo");
+
+    let error = parse("task greet {\n    echo {\n}:shell\n").unwrap_err();
+    assert_eq!(error.expected, vec!["`}:`".to_string()]);
+    assert_eq!((error.line, error.column), (1, 12));
+}
+
+/// the body of a task or block, from the opening `{` up to the `}` that matches it and is followed by `:`.
+/// braces inside string literals, powershell here-strings, triple quoted strings and shell heredocs
+/// don't count, so bodies can contain `}:` as long as it is quoted or balanced.
+/// quotes only start a string when they are closed on the same line, so a stray `don't` is just text
+pub fn parse_body(input: &str) -> PResult<'_, &str> {
+    let (body, _) = char('{')(input)?;
+    let bytes = body.as_bytes();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &body[i..];
+        if let Some(skip) = skip_literal(rest) {
+            i += skip;
+            continue;
+        }
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' if depth == 0 && rest.starts_with("}:") => return Ok((rest, &body[..i])),
+            b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    // point at the opening brace that never got closed
+    Err(Err::Error(SyntaxError {
+        input,
+        expected: vec!["`}:`".to_string()],
+    }))
+}
+
+/// length of the string literal, here-string or heredoc starting at `input`, if there is one
+fn skip_literal(input: &str) -> Option<usize> {
+    let until = |open: &str, close: &str| {
+        input[open.len()..]
+            .find(close)
+            .map(|end| open.len() + end + close.len())
+    };
+
+    for (open, close) in [("@'", "'@"), ("@\"", "\"@"), ("\"\"\"", "\"\"\""), ("'''", "'''")] {
+        if input.starts_with(open) {
+            return until(open, close);
+        }
+    }
+    if input.starts_with("<<") {
+        return skip_heredoc(input);
+    }
+
+    let quote = input.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let mut escaped = false;
+    for (i, c) in input.char_indices().skip(1) {
+        match c {
+            '\n' => return None,
+            '\\' if !escaped => escaped = true,
+            // powershell escapes with a backtick in double quoted strings, e.g "say `"hi`""
+            '`' if quote == '"' && !escaped => escaped = true,
+            c if c == quote && !escaped => return Some(i + 1),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// `<<EOF`, `<<-EOF`, `<<'EOF'` or `<<"EOF"` up to the line holding only the delimiter
+fn skip_heredoc(input: &str) -> Option<usize> {
+    let header = input[2..].trim_start_matches('-').trim_start_matches(['\'', '"']);
+    let delimiter_len = header
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(header.len());
+    let delimiter = &header[..delimiter_len];
+    if delimiter.is_empty() || delimiter.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut offset = input.find('\n')? + 1;
+    for line in input[offset..].split_inclusive('\n') {
+        if line.trim() == delimiter {
+            return Some(offset + line.trim_end_matches('\n').len());
+        }
+        offset += line.len();
+    }
+    None
+}
+
 #[test]
 fn test_parse_runtime() {
     let input = r#"
@@ -396,31 +935,36 @@ fn test_parse_runtime() {
             }:moto
         "#;
 
-    let (input, result) = parse_runtime(input).unwrap();
+    let (_, result) = parse_runtime(input).unwrap();
     assert_eq!(
-        result,
+        result.without_spans(),
         Runtime {
             identifer: Identifier::new("dart"),
             children: vec![
                 Cell::Assignment(Assignment::new("version", "3.7.0")),
              Cell::Assignment(Assignment{
                     identifier:"path".into(),
-                    value: "path/to/dart.exe".into()
+                    value: "path/to/dart.exe".into(),
+                    span: Span::default(),
                 }),
                 Cell::Task(Task {
                     identifer: Identifier::new("build"),
-                    body: String::from("echo \"Building with dart\" [:path] --version [:path] run [:file]".to_string()),
-                    runtime: Identifier::new("shell")
+                    signature: TaskSignature::default(),
+                    body: String::from("\n                echo \"Building with dart\" \n                [:path] --version [:path] \n                run [:file] "),
+                    runtime: Identifier::new("shell"),
+                    span: Span::default(),
                 })
             ],
-            runtime: "moto".into()
+            runtime: "moto".into(),
+            span: Span::default(),
         }
     );
 }
 
-pub fn parse_runtime(input: &str) -> IResult<&str, Runtime> {
+pub fn parse_runtime(input: &str) -> PResult<'_, Runtime> {
     let (input, _) = ignore_comments_and_spaces(input)?;
-    let (input, _) = tag("runtime")(input)?;
+    let start = input;
+    let (input, _) = context("`runtime`", tag("runtime"))(input)?;
     let (input, _) = multispace1(input)?;
     let (input, identifier) = parse_identifier(input)?;
     let (input, _) = ignore_comments_and_spaces(input)?;
@@ -428,20 +972,24 @@ pub fn parse_runtime(input: &str) -> IResult<&str, Runtime> {
     let (input, _) = ignore_comments_and_spaces(input)?;
 
     // Parse children (assignments or tasks) until the closing tag of the runtime block
-    let (input, children) = many0(alt((
+    let (input, children) = parse_children(input, alt((
         map(parse_assignment, Cell::Assignment),
         map(parse_task, Cell::Task),
-    )))(input)?;
+    )))?;
 
     let (input, _) = ignore_comments_and_spaces(input)?;
-    let (input, _) = tag("}:")(input)?;
-    let (input, runtime) = parse_identifier(input)?;
+    let (input, _) = closing_tag(input)?;
+    let (input, runtime) = parse_qualified_identifier(input)?;
+    let span = Span::between(start, input);
     let (input, _) = ignore_comments_and_spaces(input)?;
     let (input, _) = opt(eof)(input)?; // Optional EOF to ensure parsing until the end of input
 
     Ok((
         input,
-        Runtime::new(identifier, runtime, children),
+        Runtime {
+            span,
+            ..Runtime::new(identifier, runtime, children)
+        },
     ))
 }
 
@@ -451,31 +999,56 @@ fn test_parse_block() {
         block developerCredits { developed by incredimo for xo.rs }:text // this is a comment
         "#;
 
-    let (input, result) = parse_block(input).unwrap();
+    let (_, result) = parse_block(input).unwrap();
     assert_eq!(
-        result,
-        Block::new("developerCredits", "developed by incredimo for xo.rs", "text")
+        result.without_spans(),
+        Block::new("developerCredits", " developed by incredimo for xo.rs ", "text")
     );
 }
 
-pub fn parse_block(input: &str) -> IResult<&str, Block> {
+#[test]
+fn test_parse_structured_block() {
+    let input = r#"
+        block config {
+            { "server": { "port": 8080, "hosts": ["a", "b"] } }
+        }:json
+        "#;
+    let (_, block) = parse_block(input).unwrap();
+    let value = block.value(&block.body).unwrap();
+    assert_eq!(value.get_path(&["server", "port"]), Some(Atom::Number(8080.0)));
+    assert_eq!(value.get_path(&["server", "hosts", "1"]), Some(Atom::string("b")));
+    assert_eq!(value.get_path(&["server", "missing"]), None);
+
+    let input = "block config {\n    server:\n      port: 8080\n}:yaml";
+    let (_, block) = parse_block(input).unwrap();
+    let value = block.value(&block.body).unwrap();
+    assert_eq!(value.get_path(&["server", "port"]), Some(Atom::Number(8080.0)));
+
+    let (_, block) = parse_block("block story { once upon a time }:text").unwrap();
+    assert_eq!(block.value(&block.body).unwrap(), Atom::string("once upon a time"));
+}
+
+pub fn parse_block(input: &str) -> PResult<'_, Block> {
     //first we will take the outer frame
+    let (input, _) = ignore_comments_and_spaces(input)?;
+    let start = input;
     let mut task_parser = tuple((
-        ignore_comments_and_spaces,
-        tag("block"),
+        context("`block`", tag("block")),
         multispace1,
         parse_identifier,
         ignore_comments_and_spaces,
-        tag("{"),
-        take_until("}:"),
-        tag("}:"),
-        parse_identifier,
+        parse_body,
+        closing_tag,
+        parse_qualified_identifier,
     ));
-    let (input, (_, _, _, identifer, _, _, body, _, runtime)) = task_parser(input)?;
+    let (input, (_, _, identifer, _, body, _, runtime)) = task_parser(input)?;
 
     Ok((
         input,
-        Block::new( identifer, body, runtime)
+        Block {
+            span: Span::between(start, input),
+            ..Block::new( identifer, body, runtime)
+        }
     ))
 }
 
@@ -485,43 +1058,57 @@ fn test_parse_import() {
         import "math.moto" as math // this is a comment
         "#;
 
-    let (input, result) = parse_import(input).unwrap();
+    let (_, result) = parse_import(input).unwrap();
     assert_eq!(
-        result,
+        result.without_spans(),
         Import::new("math.moto", "math")
     );
+
+    let (input, result) = parse_import("import math as m;\n    let x = 5;").unwrap();
+    assert_eq!(result.clone().without_spans(), Import::new("math", "m"));
+    assert_eq!(result.to_string(), "import math as m;");
+    assert_eq!(result.file(), Path::new("math.moto"));
+    assert_eq!(input, "\n    let x = 5;");
+
+    let (_, result) = parse_import("import \"../shared/tools.moto\";").unwrap();
+    assert_eq!(result.clone().without_spans(), Import::exposed("../shared/tools.moto"));
+    assert_eq!(result.to_string(), "import \"../shared/tools.moto\";");
 }
 
-pub fn parse_import(input: &str) -> IResult<&str, Import> {
+/// `import "path" as alias`, the path can also be a bare name like `import math as m;`.
+/// the alias and the trailing `;` are optional
+pub fn parse_import(input: &str) -> PResult<'_, Import> {
     let (input, _) = ignore_comments_and_spaces(input)?;
-    let (input, _) = tag("import")(input)?;
+    let start = input;
+    let (input, _) = context("`import`", tag("import"))(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, path) = parse_string(input)?;
-    let (input, _) = ignore_comments_and_spaces(input)?;
-    let (input, _) = tag("as")(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, alias) = parse_identifier(input)?;
-    Ok((input, Import { path, alias }))
+    let (input, path) = alt((parse_string, map(parse_identifier, String::from)))(input)?;
+    let (input, alias) = opt(preceded(
+        tuple((ignore_comments_and_spaces, tag("as"), multispace1)),
+        parse_identifier,
+    ))(input)?;
+    let (input, _) = opt(preceded(multispace0, char(';')))(input)?;
+    Ok((input, Import { path, alias, span: Span::between(start, input) }))
 }
 
 #[test]
 fn test_parse_identifier() {
     let input = r#"x"#;
-    let (input, result) = parse_identifier(input).unwrap();
+    let (_, result) = parse_identifier(input).unwrap();
     assert_eq!(
         result,
         "x".into()
     );
 
     let input = r#"projectName"#;
-    let (input, result) = parse_identifier(input).unwrap();
+    let (_, result) = parse_identifier(input).unwrap();
     assert_eq!(
         result,
         Identifier::new("projectName")
     );
 
     let input = r#"project_name"#;
-    let (input, result) = parse_identifier(input).unwrap();
+    let (_, result) = parse_identifier(input).unwrap();
     assert_eq!(
         result,
        "project_name".into()
@@ -530,21 +1117,21 @@ fn test_parse_identifier() {
     let input = r#"
         project_name
         "#;
-    let (input, result) = parse_identifier(input).unwrap();
+    let (_, result) = parse_identifier(input).unwrap();
     assert_eq!(
         result,
         "project_name".into()
     );
 }
 
-pub fn parse_identifier(input: &str) -> IResult<&str, Identifier> {
+pub fn parse_identifier(input: &str) -> PResult<'_, Identifier> {
     let (input, _) = multispace0(input)?;
-    let (input, name) = recognize(pair(
+    let (input, name) = context("identifier", recognize(pair(
         one_of("_abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ"),
         many0(one_of(
             "_abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789",
         )),
-    ))(input)?;
+    )))(input)?;
     let (input, _) = multispace0(input)?;
     Ok((
         input,
@@ -552,22 +1139,96 @@ pub fn parse_identifier(input: &str) -> IResult<&str, Identifier> {
     ))
 }
 
+#[test]
+fn test_parse_qualified_identifier() {
+    let (input, result) = parse_qualified_identifier("r:rust\n").unwrap();
+    assert_eq!(input, "");
+    assert_eq!(result, Identifier::new("r:rust"));
+
+    let (_, result) = parse_qualified_identifier("ps").unwrap();
+    assert_eq!(result, Identifier::new("ps"));
+
+    let (_, result) = parse_invocation("[:im:rust:install(fast)]").unwrap();
+    assert_eq!(result.name(), "im:rust:install");
+}
+
+/// a name qualified with the packages it lives in, e.g `rust:install` or the tail `r:rust`
+pub fn parse_qualified_identifier(input: &str) -> PResult<'_, Identifier> {
+    let (input, first) = parse_identifier(input)?;
+    let (input, rest) = many0(preceded(char(':'), parse_identifier))(input)?;
+    let name = std::iter::once(first.0)
+        .chain(rest.into_iter().map(|identifier| identifier.0))
+        .collect::<Vec<_>>()
+        .join(":");
+    Ok((input, Identifier(name)))
+}
+
+#[test]
+fn test_parse_expression() {
+    let (_, result) = parse_atom("1 + 2 * 3").unwrap();
+    assert_eq!(
+        result,
+        Atom::binary_operation(1.0, "+", Atom::binary_operation(2.0, "*", 3.0))
+    );
+
+    let (_, result) = parse_atom("(1 + 2) * -3").unwrap();
+    assert_eq!(
+        result,
+        Atom::binary_operation(Atom::binary_operation(1.0, "+", 2.0), "*", -3.0)
+    );
+
+    let (input, result) = parse_atom("[:root] + \"/target\";").unwrap();
+    assert_eq!(input, ";");
+    assert_eq!(
+        result,
+        Atom::binary_operation(Atom::Variable(Box::new(Variable::new("root", None))), "+", "/target")
+    );
+
+    let (_, result) = parse_atom("[:rust:version] >= 2 && ![:config.debug=false] || null == null").unwrap();
+    let comparison = Atom::binary_operation(Atom::Variable(Box::new(Variable::new("rust:version", None))), ">=", 2.0);
+    let negation = Atom::unary_operation("!", Atom::Variable(Box::new(Variable::new("config.debug", false))));
+    assert_eq!(
+        result,
+        Atom::binary_operation(
+            Atom::binary_operation(comparison, "&&", negation),
+            "||",
+            Atom::binary_operation(Atom::Null, "==", Atom::Null)
+        )
+    );
+
+    let (input, result) = parse_atom("10 - 4 - 3 // comment").unwrap();
+    assert_eq!(input, " // comment");
+    assert_eq!(
+        result,
+        Atom::binary_operation(Atom::binary_operation(10.0, "-", 4.0), "-", 3.0)
+    );
+}
+
 #[test]
 fn test_parse_atom() {
     let input = r#"5"#;
-    let (input, result) = parse_atom(input).unwrap();
+    let (_, result) = parse_atom(input).unwrap();
     assert_eq!(result, Atom::Number(5.0));
 
     let input = r#""hello""#;
-    let (input, result) = parse_atom(input).unwrap();
+    let (_, result) = parse_atom(input).unwrap();
     assert_eq!(result, Atom::String("hello".to_string()));
 
     let input = r#"true"#;
-    let (input, result) = parse_atom(input).unwrap();
+    let (_, result) = parse_atom(input).unwrap();
     assert_eq!(result, Atom::Boolean(true));
 
+    let (_, result) = parse_atom("null").unwrap();
+    assert_eq!(result, Atom::Null);
+
+    let (_, result) = parse_atom("-2.5e1").unwrap();
+    assert_eq!(result, Atom::Number(-25.0));
+
+    let (_, result) = parse_atom(r#""""#).unwrap();
+    assert_eq!(result, Atom::string(""));
+
     let input = r#"[1,2,3]"#;
-    let (input, result) = parse_atom(input).unwrap();
+    let (_, result) = parse_atom(input).unwrap();
     assert_eq!(
         result,
         Atom::Array(Box::new(Array {
@@ -576,7 +1237,7 @@ fn test_parse_atom() {
     );
 
     let input = r#"{a:1, b:2}"#;
-    let (input, result) = parse_atom(input).unwrap();
+    let (_, result) = parse_atom(input).unwrap();
     assert_eq!(
         result,
         Atom::Object(Box::new(Object {
@@ -610,22 +1271,161 @@ fn test_parse_atom() {
     // })));
 }
 
-pub fn parse_atom(input: &str) -> IResult<&str, Atom> {
+/// a value, possibly computed, e.g `5` or `[:root] + "/target"` or `-(1 + 2) * [:x] >= 3 && [:ok]`.
+/// operators bind from `||`, the loosest, through `&&`, comparisons and `+ -` to `* /`, the tightest
+pub fn parse_atom(input: &str) -> PResult<'_, Atom> {
+    context("value", parse_or)(input)
+}
+
+fn parse_or(input: &str) -> PResult<'_, Atom> {
+    parse_binary_level(input, &["||"], parse_and)
+}
+
+fn parse_and(input: &str) -> PResult<'_, Atom> {
+    parse_binary_level(input, &["&&"], parse_comparison)
+}
+
+fn parse_comparison(input: &str) -> PResult<'_, Atom> {
+    parse_binary_level(input, &["==", "!=", ">=", "<=", ">", "<"], parse_sum)
+}
+
+fn parse_sum(input: &str) -> PResult<'_, Atom> {
+    parse_binary_level(input, &["+", "-"], parse_product)
+}
+
+fn parse_product(input: &str) -> PResult<'_, Atom> {
+    parse_binary_level(input, &["*", "/"], parse_unary)
+}
+
+/// left associative operators of one precedence level between `operand`s.
+/// an operator without an operand after it is left alone, e.g the `//` of a trailing comment
+fn parse_binary_level<'a>(
+    input: &'a str,
+    operators: &[&str],
+    operand: fn(&'a str) -> PResult<'a, Atom>,
+) -> PResult<'a, Atom> {
+    let (mut input, mut left) = operand(input)?;
+    loop {
+        let rest = input.trim_start();
+        let Some(operator) = operators.iter().find(|operator| rest.starts_with(**operator)) else {
+            break;
+        };
+        let Ok((rest, right)) = operand(&rest[operator.len()..]) else {
+            break;
+        };
+        left = Atom::binary_operation(left, *operator, right);
+        input = rest;
+    }
+    Ok((input, left))
+}
+
+/// `-x` and `!x`. a minus in front of a number is part of the number
+fn parse_unary(input: &str) -> PResult<'_, Atom> {
+    let (input, _) = multispace0(input)?;
+    alt((
+        map(preceded(char('-'), parse_unary), |operand| match operand {
+            Atom::Number(value) => Atom::Number(-value),
+            operand => Atom::unary_operation("-", operand),
+        }),
+        map(preceded(char('!'), parse_unary), |operand| Atom::unary_operation("!", operand)),
+        parse_primary,
+    ))(input)
+}
+
+fn parse_primary(input: &str) -> PResult<'_, Atom> {
     alt((
         map(parse_number, Atom::Number),
         map(parse_string, Atom::String),
         map(parse_boolean, Atom::Boolean),
+        map(tag("null"), |_| Atom::Null),
+        map(parse_function, |x| Atom::Function(Box::new(x))),
+        map(parse_variable_reference, |x| Atom::Variable(Box::new(x))),
         map(parse_array, |x| Atom::Array(Box::new(x))),
         map(parse_object, |x| Atom::Object(Box::new(x))),
-        map(parse_binary_operation, |x| {
-            Atom::BinaryOperation(Box::new(x))
-        }),
-        // map(parse_variable_atom, |x| Atom::Variable(Box::new(x))),
-        map(parse_function, |x| Atom::Function(Box::new(x))),
+        delimited(char('('), parse_atom, preceded(multispace0, context("`)`", char(')')))),
     ))(input)
 }
 
-pub fn parse_variable_atom(input: &str) -> IResult<&str, Variable> {
+/// a variable used in an expression, the name can be qualified and reach into blocks,
+/// e.g `[:root]`, `[:rust:version]` or `[:config.server.port=8080]`
+pub fn parse_variable_reference(input: &str) -> PResult<'_, Variable> {
+    let (input, _) = tag("[:")(input)?;
+    let (rest, name) = parse_variable_name(input)?;
+    // `[:port::number]` and `[:tag::/v\d+/]` are typed, `[:rust:version]` and `[:config:file]` are qualified
+    let (name, rest, constraint) = match name.split_once("::") {
+        Some((name, "")) => {
+            let (rest, constraint) = parse_variable_type(rest)?;
+            (name, rest, Some(constraint))
+        }
+        Some((name, kind)) => match parse_variable_type(kind) {
+            Ok(("", constraint)) => (name, rest, Some(constraint)),
+            _ => return Err(Err::Error(SyntaxError::from_error_kind(kind, ErrorKind::Verify))),
+        },
+        None => (name, rest, None),
+    };
+    let (rest, variable) = parse_variable_value(name, rest)?;
+    let (rest, _) = char(']')(rest)?;
+    Ok((rest, typed(input, variable, constraint)?))
+}
+
+/// the `[:variables]` of a task or block body in order of appearance, a name used twice is listed twice.
+/// calls like `[:install()]` are not variables. a default that is not a value, e.g `[:dir=C:\tools]`,
+/// is kept as the text it is written as
+pub fn parse_body_variables(body: &str) -> Vec<Variable> {
+    let mut raw = delimited(tag("[:"), pair(parse_variable_name, preceded(char('='), is_not("]"))), char(']'));
+    body.match_indices("[:")
+        .filter_map(|(start, _)| match parse_variable_reference(&body[start..]) {
+            Ok((_, variable)) => Some(variable),
+            Err(_) => {
+                let (_, (name, default)) = raw(&body[start..]).ok()?;
+                Some(Variable::new(name, default.trim()))
+            }
+        })
+        .collect()
+}
+
+/// the name in a `[:name]` of a body, qualified like `rust:version` or a member like `env.HOME`
+fn parse_variable_name(input: &str) -> PResult<'_, &str> {
+    recognize(pair(
+        one_of("_abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ"),
+        take_while(|c: char| c.is_ascii_alphanumeric() || "_:.".contains(c)),
+    ))(input)
+}
+
+/// `variable` with the type written after its name, if there is one.
+/// a type and alternatives together, `[:port::number=(80 | 443)]`, are an error, the alternatives are enough
+fn typed(input: &str, variable: Variable, constraint: Option<VariableConstraint>) -> Result<Variable, Err<SyntaxError<'_>>> {
+    match (constraint, variable.constraint.is_some()) {
+        (Some(_), true) => Err(Err::Error(SyntaxError::from_error_kind(input, ErrorKind::Verify))),
+        (Some(constraint), false) => Ok(Variable { constraint: Some(constraint), ..variable }),
+        (None, _) => Ok(variable),
+    }
+}
+
+/// what follows the name of a variable: nothing, `=` and a default, or `=` and alternatives like `(start | stop)`
+fn parse_variable_value<'a>(name: &str, input: &'a str) -> PResult<'a, Variable> {
+    let (input, value) = opt(preceded(char('='), alt((map(parse_one_of, Ok), map(parse_atom, Err)))))(input)?;
+    let variable = match value {
+        Some(Ok(choices)) => Variable::one_of(name, choices),
+        Some(Err(default)) => Variable::new(name, default),
+        None => Variable::new(name, Atom::Null),
+    };
+    Ok((input, variable))
+}
+
+/// two or more alternatives in parentheses, e.g `(start | stop)` or `(80 | 443 | "any port")`.
+/// an alternative is a quoted string or a bare word, bare numbers are numbers
+pub fn parse_one_of(input: &str) -> PResult<'_, Vec<Atom>> {
+    let bare = take_while1(|c: char| !c.is_whitespace() && !"|()[]\"'".contains(c));
+    let choice = delimited(multispace0, alt((map(parse_string, Atom::String), map(bare, |word: &str| Atom::from(word.to_string())))), multispace0);
+    let (rest, choices) = delimited(char('('), separated_list1(char('|'), choice), char(')'))(input)?;
+    if choices.len() < 2 {
+        return Err(Err::Error(SyntaxError::from_error_kind(input, ErrorKind::SeparatedList)));
+    }
+    Ok((rest, choices))
+}
+
+pub fn parse_variable_atom(input: &str) -> PResult<'_, Variable> {
     let (input, _) = char('[')(input)?;
     let (input, identifier) = parse_identifier(input)?;
     let (input, default) = opt(preceded(char('='), parse_atom))(input)?;
@@ -636,7 +1436,7 @@ pub fn parse_variable_atom(input: &str) -> IResult<&str, Variable> {
     ))
 }
 
-pub fn parse_binary_operator(input: &str) -> IResult<&str, Operator> {
+pub fn parse_binary_operator(input: &str) -> PResult<'_, Operator> {
     let (input, _) = ignore_comments_and_spaces(input)?;
     let (input, operator) = alt((
         tag("+"),
@@ -664,48 +1464,118 @@ pub fn parse_binary_operator(input: &str) -> IResult<&str, Operator> {
 #[test]
 fn test_parse_number() {
     let input = r#"5"#;
-    let (input, result) = parse_number(input).unwrap();
+    let (_, result) = parse_number(input).unwrap();
     assert_eq!(result, 5.0);
+
+    assert_eq!(parse_number("3.7;").unwrap(), (";", 3.7));
+    assert_eq!(parse_number("-1").unwrap(), ("", -1.0));
+    assert_eq!(parse_number("1.5e3").unwrap(), ("", 1500.0));
+    assert_eq!(parse_number("2E-2").unwrap(), ("", 0.02));
+    // a version is not a number, the dot after `1.0` is left alone
+    assert_eq!(parse_number("1.0.0").unwrap(), (".0", 1.0));
+    assert_eq!(parse_number("7.").unwrap(), (".", 7.0));
+    assert!(parse_number("-x").is_err());
 }
 
-pub fn parse_number(input: &str) -> IResult<&str, f64> {
-    let (input, number) = digit1(input)?;
-    Ok((input, number.parse().unwrap()))
+/// `5`, `-1`, `3.7` or `1.5e3`
+pub fn parse_number(input: &str) -> PResult<'_, f64> {
+    let (input, number) = recognize(tuple((
+        opt(char('-')),
+        digit1,
+        opt(pair(char('.'), digit1)),
+        opt(tuple((one_of("eE"), opt(one_of("+-")), digit1))),
+    )))(input)?;
+    Ok((input, number.parse().unwrap_or_default()))
 }
 
 #[test]
 fn test_parse_string() {
     let input = r#""hello""#;
-    let (input, result) = parse_string(input).unwrap();
+    let (_, result) = parse_string(input).unwrap();
     assert_eq!(result, "hello".to_string());
+
+    assert_eq!(parse_string(r#""""#).unwrap(), ("", String::new()));
+    assert_eq!(
+        parse_string(r#""say \"hi\"\n\tand \\ \u{1F600} \u00e9" rest"#).unwrap(),
+        (" rest", "say \"hi\"\n\tand \\ \u{1F600} \u{e9}".to_string())
+    );
+    assert_eq!(parse_string("\"two\nlines\"").unwrap().1, "two\nlines");
+    assert!(parse_string(r#""bad \q escape""#).is_err());
+    assert!(parse_string(r#""never closed"#).is_err());
+
+    let (input, result) = parse_string("\"\"\"raw \\n \"quotes\" and\nlines\"\"\";").unwrap();
+    assert_eq!(input, ";");
+    assert_eq!(result, "raw \\n \"quotes\" and\nlines");
+}
+
+/// a quoted string. `\"`, `\\`, `\n`, `\r`, `\t`, `\0`, `\u00e9` and `\u{1F600}` are escapes,
+/// strings between `"""` are taken as they are and can hold quotes
+pub fn parse_string(input: &str) -> PResult<'_, String> {
+    if let Some(raw) = input.strip_prefix("\"\"\"") {
+        let (input, string) = context("`\"\"\"`", take_until("\"\"\""))(raw)?;
+        return Ok((&input[3..], string.to_string()));
+    }
+
+    let (mut input, _) = char('"')(input)?;
+    let mut string = String::new();
+    loop {
+        let mut chars = input.chars();
+        match chars.next() {
+            None => return Err(Err::Error(SyntaxError::from_char(input, '"'))),
+            Some('"') => return Ok((chars.as_str(), string)),
+            Some('\\') => {
+                let (rest, escaped) = parse_escape(chars.as_str()).map_err(|_| {
+                    Err::Failure(SyntaxError {
+                        input,
+                        expected: vec!["an escape like `\\n`, `\\\"` or `\\u{e9}`".to_string()],
+                    })
+                })?;
+                string.push(escaped);
+                input = rest;
+            }
+            Some(c) => {
+                string.push(c);
+                input = chars.as_str();
+            }
+        }
+    }
 }
 
-pub fn parse_string(input: &str) -> IResult<&str, String> {
-    let (input, _) = char('"')(input)?;
-    let (input, string) = is_not("\"")(input)?;
-    let (input, _) = char('"')(input)?;
-    Ok((input, string.to_string()))
+/// the character after a `\` in a string
+fn parse_escape(input: &str) -> PResult<'_, char> {
+    let unicode = |digits: &str| u32::from_str_radix(digits, 16).ok().and_then(char::from_u32);
+    alt((
+        map(char('"'), |_| '"'),
+        map(char('\\'), |_| '\\'),
+        map(char('/'), |_| '/'),
+        map(char('n'), |_| '\n'),
+        map(char('r'), |_| '\r'),
+        map(char('t'), |_| '\t'),
+        map(char('0'), |_| '\0'),
+        map_opt(preceded(tag("u{"), terminated(hex_digit1, char('}'))), unicode),
+        map_opt(preceded(char('u'), recognize(count(one_of("0123456789abcdefABCDEF"), 4))), unicode),
+    ))(input)
 }
 
 #[test]
 fn test_parse_boolean() {
     let input = r#"true"#;
-    let (input, result) = parse_boolean(input).unwrap();
-    assert_eq!(result, true);
+    let (_, result) = parse_boolean(input).unwrap();
+    assert!(result);
 
     let input = r#"false"#;
-    let (input, result) = parse_boolean(input).unwrap();
-    assert_eq!(result, false);
+    let (_, result) = parse_boolean(input).unwrap();
+    assert!(!result);
 }
 
-pub fn parse_boolean(input: &str) -> IResult<&str, bool> {
+pub fn parse_boolean(input: &str) -> PResult<'_, bool> {
     alt((map(tag("true"), |_| true), map(tag("false"), |_| false)))(input)
 }
 
 #[test]
 fn test_parse_array() {
     let input = r#"[1,2,3]"#;
-    let (input, result) = parse_array(input).unwrap();
+    let (_, result) = parse_array(input).unwrap();
     assert_eq!(
         result,
         Array {
@@ -714,7 +1584,7 @@ fn test_parse_array() {
     );
 }
 
-pub fn parse_array(input: &str) -> IResult<&str, Array> {
+pub fn parse_array(input: &str) -> PResult<'_, Array> {
     let (input, _) = char('[')(input)?;
     let (input, values) = separated_list0(char(','), parse_atom)(input)?;
     let (input, _) = char(']')(input)?;
@@ -724,7 +1594,7 @@ pub fn parse_array(input: &str) -> IResult<&str, Array> {
 #[test]
 fn test_parse_object() {
     let input = r#"{a:1, b:2}"#;
-    let (input, result) = parse_object(input).unwrap();
+    let (_, result) = parse_object(input).unwrap();
     assert_eq!(
         result,
         Object {
@@ -736,14 +1606,14 @@ fn test_parse_object() {
     );
 }
 
-pub fn parse_object(input: &str) -> IResult<&str, Object> {
+pub fn parse_object(input: &str) -> PResult<'_, Object> {
     let (input, _) = char('{')(input)?;
     let (input, values) = separated_list0(char(','), parse_key_value_pair)(input)?;
     let (input, _) = char('}')(input)?;
     Ok((input, Object { values }))
 }
 
-pub fn parse_key_value_pair(input: &str) -> IResult<&str, (String, Atom)> {
+pub fn parse_key_value_pair(input: &str) -> PResult<'_, (String, Atom)> {
     let (input, _) = ignore_comments_and_spaces(input)?;
     let (input, key) = is_not(":")(input)?;
     let (input, _) = char(':')(input)?;
@@ -754,7 +1624,7 @@ pub fn parse_key_value_pair(input: &str) -> IResult<&str, (String, Atom)> {
 #[test]
 fn test_parse_binary_operation() {
     let input = r#"5 + 5"#;
-    let (input, result) = parse_binary_operation(input).unwrap();
+    let (_, result) = parse_binary_operation(input).unwrap();
     assert_eq!(
         result,
         BinaryOperation {
@@ -767,26 +1637,21 @@ fn test_parse_binary_operation() {
     );
 }
 
-pub fn parse_binary_operation(input: &str) -> IResult<&str, BinaryOperation> {
-    let (input, left) = parse_atom(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, operator) = parse_operator(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, right) = parse_atom(input)?;
-    Ok((
-        input,
-        BinaryOperation {
-            left,
-            operator,
-            right,
-        },
-    ))
+/// an expression whose outermost part is an operator, e.g `5 + 5`
+pub fn parse_binary_operation(input: &str) -> PResult<'_, BinaryOperation> {
+    match parse_atom(input)? {
+        (rest, Atom::BinaryOperation(operation)) => Ok((rest, *operation)),
+        _ => Err(Err::Error(SyntaxError {
+            input,
+            expected: vec!["an operator".to_string()],
+        })),
+    }
 }
 
 #[test]
 fn test_parse_operator() {
     let input = r#"+"#;
-    let (input, result) = parse_operator(input).unwrap();
+    let (_, result) = parse_operator(input).unwrap();
     assert_eq!(
         result,
         Operator {
@@ -795,7 +1660,7 @@ fn test_parse_operator() {
     );
 }
 
-pub fn parse_operator(input: &str) -> IResult<&str, Operator> {
+pub fn parse_operator(input: &str) -> PResult<'_, Operator> {
     let (input, operator) = alt((
         tag("+"),
         tag("-"),
@@ -803,10 +1668,10 @@ pub fn parse_operator(input: &str) -> IResult<&str, Operator> {
         tag("/"),
         tag("=="),
         tag("!="),
-        tag(">"),
-        tag("<"),
         tag(">="),
         tag("<="),
+        tag(">"),
+        tag("<"),
         tag("&&"),
         tag("||"),
     ))(input)?;
@@ -821,58 +1686,74 @@ pub fn parse_operator(input: &str) -> IResult<&str, Operator> {
 #[test]
 fn test_parse_variable() {
     let input = r#"[:name]"#;
-    let (input, result) = parse_variable(input).unwrap();
+    let (_, result) = parse_variable(input).unwrap();
     assert_eq!(
         result,
         Variable::new("name", None)
     );
 
     let input = r#"[:x=5]"#;
-    let (input, result) = parse_variable(input).unwrap();
+    let (_, result) = parse_variable(input).unwrap();
     assert_eq!(
         result,
         Variable::new("x", Atom::Number(5.0))
     );
 
     let input = r#"[:x="hello"]"#;
-    let (input, result) = parse_variable(input).unwrap();
+    let (_, result) = parse_variable(input).unwrap();
     assert_eq!(
         result,
         Variable::new("x", "hello")
     );
 
     let input = r#"[:x=true]"#;
-    let (input, result) = parse_variable(input).unwrap();
+    let (_, result) = parse_variable(input).unwrap();
     assert_eq!(
         result,
         Variable::new("x", true)
     );
 }
 
-pub fn parse_variable(input: &str) -> IResult<&str,  Variable> {
+pub fn parse_variable(input: &str) -> PResult<'_, Variable> {
     let (input, _) = tag("[:")(input)?;
     let (input, identifier) = parse_identifier(input)?;
-    let (input, default) = opt(preceded(char('='), parse_atom))(input)?;
-    let (input, _) = tag("]")(input)?;
-    Ok((
-        input,
-         Variable::new(identifier, default)
-    ))
+    let (rest, constraint) = opt(preceded(tag("::"), parse_variable_type))(input)?;
+    let (rest, variable) = parse_variable_value(&identifier.0, rest)?;
+    let (rest, _) = tag("]")(rest)?;
+    Ok((rest, typed(input, variable, constraint)?))
+}
+
+/// the type of a parameter, after its name in `[:port::number]`: `number`, `bool`, `file`
+/// or a regular expression between slashes like `/v\d+/`, where `\/` is a slash
+pub fn parse_variable_type(input: &str) -> PResult<'_, VariableConstraint> {
+    let pattern = delimited(char('/'), escaped(none_of("\\/"), '\\', anychar), char('/'));
+    let (rest, constraint) = alt((
+        map(tag("number"), |_| VariableConstraint::Number),
+        map(tag("bool"), |_| VariableConstraint::Bool),
+        map(tag("file"), |_| VariableConstraint::File),
+        map(pattern, |pattern: &str| VariableConstraint::Matches(pattern.replace("\\/", "/"))),
+    ))(input)?;
+    match &constraint {
+        VariableConstraint::Matches(pattern) if regex::Regex::new(pattern).is_err() => {
+            Err(Err::Error(SyntaxError::from_error_kind(input, ErrorKind::Verify)))
+        }
+        _ => Ok((rest, constraint)),
+    }
 }
 
 #[test]
 fn test_parse_function() {
     let input = r#"[:print("hello world")]"#;
-    let (input, result) = parse_function(input).unwrap();
+    let (_, result) = parse_function(input).unwrap();
     assert_eq!(
         result,
         Function::new("print", vec![Atom::String("hello world".to_string())])
     );
 }
 
-pub fn parse_function(input: &str) -> IResult<&str, Function> {
+pub fn parse_function(input: &str) -> PResult<'_, Function> {
     let (input, _) = tag("[:")(input)?;
-    let (input, identifier) = parse_identifier(input)?;
+    let (input, identifier) = parse_qualified_identifier(input)?;
     let (input, arguments) =
         delimited(char('('), separated_list0(char(','), parse_atom), char(')'))(input)?;
     let (input, _) = tag("]")(input)?;
@@ -886,8 +1767,91 @@ pub fn parse_function(input: &str) -> IResult<&str, Function> {
 }
 
 
+#[test]
+fn test_parse_invocation() {
+    let (_, result) = parse_invocation("[:list_processes()]").unwrap();
+    assert_eq!(result.without_spans(), Invocation::new("list_processes", vec![]));
+
+    let (_, result) = parse_invocation("[:kill_process(process_id: 1234)];").unwrap();
+    assert_eq!(
+        result.without_spans(),
+        Invocation::new("kill_process", vec![Argument::named("process_id", 1234.0)])
+    );
+
+    let (_, result) = parse_invocation("[:add(5, a:1)]").unwrap();
+    assert_eq!(
+        result.clone().without_spans(),
+        Invocation::new("add", vec![Argument::positional(5.0), Argument::named("a", 1.0)])
+    );
+    let task = Task::new("add", "let result = [:a] + [:b]", "moto");
+    assert_eq!(
+        result.bind(&task),
+        vec![("a".into(), Atom::Number(1.0)), ("b".into(), Atom::Number(5.0))]
+    );
+
+    let (_, result) = parse_invocation("[:greet_from_dart(compile, \"hello\")]").unwrap();
+    assert_eq!(
+        result.without_spans(),
+        Invocation::new("greet_from_dart", vec![Argument::positional("compile"), Argument::positional("hello")])
+    );
+
+    let input = r#"
+    package processes {
+        task list_processes {
+            Get-Process
+        }:ps
+        [:list_processes()]
+    }:moto"#;
+    let (_, result) = parse_cell(input).unwrap();
+    let Cell::Package(package) = result.without_spans() else { panic!("expected a package") };
+    assert_eq!(package.invocations(), vec![Invocation::new("list_processes", vec![])]);
+}
+
+/// parses a task call written as a statement, e.g `[:kill_process(process_id: 1234)]`.
+/// a trailing `;` is allowed
+pub fn parse_invocation(input: &str) -> PResult<'_, Invocation> {
+    let (input, _) = ignore_comments_and_spaces(input)?;
+    let start = input;
+    let (input, _) = tag("[:")(input)?;
+    let (input, identifier) = parse_qualified_identifier(input)?;
+    let (input, _) = char('(')(input)?;
+    let (input, arguments) = separated_list0(char(','), parse_argument)(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = context("`)`", char(')'))(input)?;
+    let (input, _) = context("`]`", char(']'))(input)?;
+    let (input, _) = opt(preceded(multispace0, char(';')))(input)?;
+    Ok((
+        input,
+        Invocation {
+            identifier,
+            arguments,
+            span: Span::between(start, input),
+        },
+    ))
+}
+
+/// parses `name: value` or a bare value. bare words like `compile` are taken as strings
+pub fn parse_argument(input: &str) -> PResult<'_, Argument> {
+    let (input, _) = multispace0(input)?;
+    let (input, name) = opt(terminated(parse_identifier, char(':')))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, value) = alt((
+        map(
+            terminated(parse_identifier, peek(one_of(",)"))),
+            |word| match word.0.as_str() {
+                "true" => Atom::Boolean(true),
+                "false" => Atom::Boolean(false),
+                "null" => Atom::Null,
+                _ => Atom::String(word.0),
+            },
+        ),
+        parse_atom,
+    ))(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, Argument { name, value }))
+}
 
-pub fn parse_text(input: &str) -> IResult<&str, String> {
+pub fn parse_text(input: &str) -> PResult<'_, String> {
     //if the input contains "[:", then we take everything before the "[:"
     let (input, text) = alt((take_until("[:"), rest))(input)?;
     Ok((input, text.to_string()))
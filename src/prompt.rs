@@ -0,0 +1,237 @@
+use super::*;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq)]
+///a `[:variable]` a task uses that has no value, the user is asked for it before the task starts
+pub struct Missing {
+    pub name: String,
+    ///what it can be, e.g `mom` or `dad` for `[:name=(mom | dad)]` or a number for `[:port::number]`. anything when `None`
+    pub constraint: Option<VariableConstraint>,
+}
+
+impl Ctx {
+    /// gets the scope ready to run `task`. parameters of the signature that were left out take their default,
+    /// values given to typed variables or variables with alternatives are checked and turned into the type,
+    /// `8080` into a number for `[:port::number]`, and the variables nothing gives a value are
+    /// asked for. when nobody can answer, stdin not being a terminal, they are an error instead,
+    /// a command is never run with an empty string in place of a variable
+    pub async fn prepare(&self, task: &Task) -> Result<(), MotoError> {
+        for parameter in task.signature.parameters().into_iter().filter(Variable::has_default) {
+            if !self.has_value(&parameter.name()).await? {
+                let value = self.evaluate(&parameter.value).await?;
+                self.set_variable(parameter.name(), value).await;
+            }
+        }
+        for variable in task.declared_variables().into_iter().filter(|variable| variable.constraint.is_some()) {
+            if let Some(value) = self.resolve_variable(variable.name()).await? {
+                let coerced = variable.coerce(&value)?;
+                if coerced != value {
+                    self.set_variable(variable.name(), coerced).await;
+                }
+            }
+        }
+
+        let missing = self.missing_variables(task).await?;
+        if missing.is_empty() {
+            return Ok(());
+        }
+        if !std::io::stdin().is_terminal() {
+            return Err(MotoError::MissingVariables { names: missing.into_iter().map(|missing| missing.name).collect() });
+        }
+        for missing in missing {
+            let value = ask(&missing)?;
+            self.set_variable(missing.name, value).await;
+        }
+        Ok(())
+    }
+
+    /// the variables of `task` that nothing gives a value: no default, no variable in scope,
+    /// no environment variable, no `let` and no block. `[:name=(mom | dad)]` has no default, it has to be picked
+    pub async fn missing_variables(&self, task: &Task) -> Result<Vec<Missing>, MotoError> {
+        let mut missing = vec![];
+        for variable in task.declared_variables() {
+            if variable.has_default() || self.has_value(&variable.name()).await? {
+                continue;
+            }
+            missing.push(Missing { name: variable.name(), constraint: variable.constraint });
+        }
+        Ok(missing)
+    }
+
+    /// whether `[:name]` has a value, found without computing it
+    async fn has_value(&self, name: &str) -> Result<bool, MotoError> {
+        if let Some(variable) = name.strip_prefix("env.") {
+            return Ok(self.environment.lock().await.get(variable).is_some());
+        }
+        let member = name.split('.').next().unwrap_or_default();
+        if !member.contains(':') && (self.get_variable(member).await.is_some() || self.given(member).await.is_some()) {
+            return Ok(true);
+        }
+        self.defines_value(name).await
+    }
+}
+
+/// reads the value of `missing` from the terminal. alternatives and yes or no are picked from a menu like the one
+/// of the tasks, so is a file among those below the current directory. other values are typed until they are valid
+fn ask(missing: &Missing) -> Result<Atom, MotoError> {
+    let choices = match &missing.constraint {
+        Some(VariableConstraint::File) => files(Path::new("."), FILE_CHOICES),
+        Some(constraint) => constraint.choices(),
+        None => vec![],
+    };
+    if !choices.is_empty() {
+        let choices = choices
+            .iter()
+            .map(|choice| AsyncChoice::new(choice, "", Arc::new(|| Pin::from(Box::new(async { 0 }))), String::new()))
+            .collect::<Vec<_>>();
+        return match display_selection_menu(&format!("pick {}", missing.name), &choices, &[])? {
+            Some(choice) => coerce(missing, choice.get_title().to_string().into()),
+            None => Err(MotoError::MissingVariables { names: vec![missing.name.clone()] }),
+        };
+    }
+    loop {
+        show!(yellow_bold, "│ ", cyan_bold, &missing.name, gray_dim, " » ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().lock().read_line(&mut line)? == 0 {
+            return Err(MotoError::MissingVariables { names: vec![missing.name.clone()] });
+        }
+        match coerce(missing, line.trim().to_string().into()) {
+            Ok(value) => return Ok(value),
+            Err(error) => {
+                showln!(yellow_bold, "│ ", red_bold, error.to_string());
+            }
+        }
+    }
+}
+
+/// `value` as the constraint of `missing` asks for it
+fn coerce(missing: &Missing, value: Atom) -> Result<Atom, MotoError> {
+    let variable = Variable { constraint: missing.constraint.clone(), ..Variable::new(missing.name.as_str(), Atom::Null) };
+    variable.coerce(&value)
+}
+
+/// how many files the picker of `[:path::file]` shows at most
+const FILE_CHOICES: usize = 50;
+
+/// up to `limit` files below `dir`, hidden ones and those in hidden directories left out.
+/// the walk stops at the last of them
+fn files(dir: &Path, limit: usize) -> Vec<String> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries = entries.flatten().map(|entry| entry.path()).collect::<Vec<_>>();
+        entries.sort();
+        for path in entries {
+            if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            files.push(path.strip_prefix(".").unwrap_or(&path).to_string_lossy().to_string());
+            if files.len() == limit {
+                return files;
+            }
+        }
+    }
+    files
+}
+
+#[test]
+fn test_files() {
+    let dir = std::env::temp_dir().join(format!("moto-files-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("nested")).unwrap();
+    for file in ["a.txt", "b.txt", ".hidden", "nested/c.txt"] {
+        std::fs::write(dir.join(file), "").unwrap();
+    }
+    let all = files(&dir, 10);
+    assert_eq!(all.len(), 3);
+    assert!(all.iter().all(|file| !file.contains(".hidden")));
+    assert_eq!(files(&dir, 2).len(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_missing_variables() {
+    let ctx = Ctx::from_source("let root = \"/srv\";\n").await;
+    ctx.set_variable("force", Atom::from("-f")).await;
+    let code = "rm -rf [:dir]/ [:force] [:root] [:user=me] [:who=(mom | \"dad\")] [:dir] [:version()]";
+    let missing = ctx.missing_variables(&Task::new("clean", code, "shell")).await.unwrap();
+    assert_eq!(
+        missing,
+        vec![
+            Missing { name: "dir".to_string(), constraint: None },
+            Missing { name: "who".to_string(), constraint: Some(VariableConstraint::OneOf(vec![Atom::from("mom"), Atom::from("dad")])) },
+        ]
+    );
+    assert!(matches!(dope(&ctx, "rm -rf [:dir]/".to_string()).await, Err(MotoError::MissingVariables { names }) if names == ["dir"]));
+    ctx.set_variable("who", Atom::from("dad")).await;
+    assert_eq!(dope(&ctx, "[:root] [:who=(mom | dad)] [:user=me]".to_string()).await.unwrap(), "/srv dad me");
+}
+
+#[tokio::test]
+async fn test_one_of() {
+    let (_, (_, signature)) = parse_task_header("control [:command=(start | stop)] {").unwrap();
+    let command = Variable::one_of("command", vec![Atom::from("start"), Atom::from("stop")]);
+    assert_eq!(signature.parameters(), vec![command.clone()]);
+    assert_eq!(signature.to_string(), "control [:command=(start | stop)]");
+    let (_, port) = parse_variable_reference("[:port=(80 | 443 | \"any port\")]").unwrap();
+    assert_eq!(port.constraint, Some(VariableConstraint::OneOf(vec![Atom::Number(80.0), Atom::Number(443.0), Atom::from("any port")])));
+    assert!(parse_variable_reference("[:x=(5)]").unwrap().1.constraint.is_none());
+
+    let task = Task { signature, ..Task::new("control", "echo [:command]", "shell") };
+    let ctx = Ctx::empty();
+    ctx.set_variable("command", Atom::from("restart")).await;
+    let error = ctx.prepare(&task).await.unwrap_err();
+    assert_eq!(error.to_string(), "restart is not a valid command, it can be start or stop");
+    assert!(matches!(dope(&ctx, "[:command=(start | stop)]".to_string()).await, Err(MotoError::InvalidValue { .. })));
+    ctx.set_variable("command", Atom::from("stop")).await;
+    assert!(ctx.prepare(&task).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_typed_parameters() {
+    let header = r"serve [:port::number=8080] [:verbose::bool] [:config::file] [:tag::/v\d+\/x/] {";
+    let (_, (_, signature)) = parse_task_header(header).unwrap();
+    let parameters = signature.parameters();
+    assert_eq!(parameters[0].constraint, Some(VariableConstraint::Number));
+    assert_eq!(parameters[0].value, Atom::Number(8080.0));
+    assert_eq!(parameters[1].constraint, Some(VariableConstraint::Bool));
+    assert_eq!(parameters[2].constraint, Some(VariableConstraint::File));
+    assert_eq!(parameters[3].constraint, Some(VariableConstraint::Matches(r"v\d+/x".to_string())));
+    assert_eq!(signature.to_string(), r"serve [:port::number=8080] [:verbose::bool] [:config::file] [:tag::/v\d+/x/]");
+    assert!(parse_variable("[:port::number=(80 | 443)]").is_err());
+    assert!(parse_variable("[:tag::/v(/]").is_err());
+
+    let task = Task { signature, ..Task::new("serve", "serve [:port] [:verbose]", "shell") };
+    let ctx = Ctx::empty();
+    ctx.set_variable("port", Atom::from("abc")).await;
+    let error = ctx.prepare(&task).await.unwrap_err();
+    assert_eq!(error.to_string(), "abc is not a valid port, expected a number");
+    ctx.set_variable("port", Atom::from("9090")).await;
+    ctx.set_variable("verbose", Atom::from("yes")).await;
+    ctx.set_variable("config", Atom::from("Cargo.toml")).await;
+    ctx.set_variable("tag", Atom::from("v12/x")).await;
+    assert!(ctx.prepare(&task).await.is_ok());
+    assert_eq!(ctx.get_variable("port").await, Some(Atom::Number(9090.0)));
+    assert_eq!(ctx.get_variable("verbose").await, Some(Atom::Boolean(true)));
+    ctx.set_variable("tag", Atom::from("v12x")).await;
+    assert!(matches!(ctx.prepare(&task).await, Err(MotoError::InvalidValue { name, .. }) if name == "tag"));
+
+    // in bodies too, where `[:rust:version]` and `[:config:file]` stay qualified names
+    assert_eq!(parse_variable_reference("[:rust:version]").unwrap().1, Variable::new("rust:version", Atom::Null));
+    assert_eq!(parse_variable_reference("[:config:file]").unwrap().1, Variable::new("config:file", Atom::Null));
+    assert_eq!(parse_variable_reference("[:tag::/v\\d+/]").unwrap().1.name(), "tag");
+    assert_eq!(parse_variable_reference("[:rust:port::number]").unwrap().1.name(), "rust:port");
+    assert_eq!(dope(&ctx, "[:port::number=8080] [:verbose::bool]".to_string()).await.unwrap(), "9090 true");
+    assert_eq!(dope(&ctx, "[:retries::number=3]".to_string()).await.unwrap(), "3");
+    assert!(matches!(dope(&ctx, "[:retries::number]".to_string()).await, Err(MotoError::MissingVariables { names }) if names == ["retries"]));
+}
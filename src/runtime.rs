@@ -1,184 +1,283 @@
-use std::pin::Pin;
-
 use crate::*;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::Command;
+use futures::Future;
+use std::pin::Pin;
 
-use crate::get_runtime;
+use async_trait::async_trait;
 
+/// runs `code` with `runtime` in the scope `ctx` and returns everything the code printed to stdout.
+/// a process exiting unsuccessfully fails with [`MotoError::NonZeroExit`]
 pub async fn execute(
+    ctx: &Ctx,
     code: impl Into<String>,
     runtime: impl Into<String>,
     runtime_task: impl Into<String>,
-) -> Result<String, String> {
+) -> Result<String, MotoError> {
     let code = code.into();
     let runtime = runtime.into();
     let runtime_task = runtime_task.into();
     let instance = std::time::Instant::now();
 
-    if runtime == "moto" {
-        execute_internal(&code, &runtime, &runtime_task).await?;
-    } else {
-        match runtime.as_str() {
-            "shell" | "sh" | "powershell" | "ps" => execute_simple_runtime(&code, &runtime).await,
-            _ => execute_complex_runtime(&code, &runtime, &runtime_task).await,
-        }?;
-    }
+    let result = match ctx.get_executor(&runtime).await {
+        Ok(executor) => executor.execute(ctx, &code, &runtime_task).await,
+        Err(error) => Err(error),
+    };
 
     let elapsed = format_elapsed_time(instance.elapsed());
-    print_elapsed_time(elapsed);
+    match &result {
+        Ok(_) => print_elapsed_time(elapsed),
+        Err(error) => print_failure(elapsed, error),
+    }
+
+    result
+}
 
+/// runs `task` in the scope `ctx` once its variables are ready, see [`Ctx::prepare`]
+pub async fn execute_task(ctx: &Ctx, task: &Task, runtime_task: impl Into<String>) -> Result<String, MotoError> {
+    if let Err(error) = ctx.prepare(task).await {
+        print_failure(format_elapsed_time(std::time::Duration::ZERO), &error);
+        return Err(error);
+    }
+    execute(ctx, task.get_code(), task.runtime(), runtime_task).await
+}
+
+/// runs the invocation cells of a package in order, e.g `[:kill_process(process_id: 1234)]`.
+/// tasks are looked up in the package first and then everywhere else. the first failing call stops the run.
+/// the package runs in its own scope
+pub async fn execute_package(ctx: &Ctx, package: &Package) -> Result<String, MotoError> {
+    let scope = ctx.within(vec![package.name()]);
+    for invocation in package.invocations() {
+        let name = invocation.name();
+        let task = match package.get_task(&name) {
+            Some(task) => Resolved { namespace: scope.namespace.clone(), cell: task },
+            None => scope.get_task(&name).await.map_err(|error| match error {
+                MotoError::TaskNotFound { name, .. } => MotoError::TaskNotFound {
+                    name,
+                    scope: Some(format!("package {}, invoked at {}", package.name(), invocation.span)),
+                },
+                error => error,
+            })?,
+        };
+        invoke(&scope.within(task.namespace), &task.cell, &invocation).await?;
+    }
     Ok("".into())
 }
 
-async fn execute_internal(code: &str, runtime: &str, runtime_task: &str) -> Result<(), String> {
-    if let Some(fx) = get_internal_function(runtime_task).await {
+/// binds the arguments of `invocation` to the variables of `task` and runs it, returning what it printed.
+/// when the task runs on a runtime defined in moto, a leading bare word naming one of the runtime's
+/// tasks picks that task instead of `run`, e.g `[:greet_from_dart(compile)]`.
+/// the arguments are bound in a scope of their own, they are gone once the task is done.
+/// `ctx` should run in the namespace the task is defined in
+pub async fn invoke(ctx: &Ctx, task: &Task, invocation: &Invocation) -> Result<String, MotoError> {
+    let mut invocation = invocation.clone();
+    let mut runtime_task = "run".to_string();
+    if let Ok(runtime) = ctx.get_runtime(task.runtime()).await {
+        if let Some(Argument { name: None, value: Atom::String(word) }) = invocation.arguments.first() {
+            if runtime.cell.get_task(word).is_some() {
+                runtime_task = word.clone();
+                invocation.arguments.remove(0);
+            }
+        }
+    }
+
+    let scope = ctx.child();
+    for (name, value) in invocation.bind(task) {
+        scope.set_variable(name, value).await;
+    }
+    execute_task(&scope, task, runtime_task).await
+}
+
+/// looks up the task named by `invocation` and invokes it.
+/// boxed since tasks called from interpolations can call tasks again
+pub fn call<'a>(ctx: &'a Ctx, invocation: &'a Invocation) -> Pin<Box<dyn Future<Output = Result<String, MotoError>> + Send + 'a>> {
+    Box::pin(async move {
+        let task = ctx.get_task(invocation.name()).await?;
+        invoke(&ctx.within(task.namespace), &task.cell, invocation).await
+    })
+}
+
+#[async_trait]
+impl Executor for Internal {
+    /// the code of internal tasks is a call like `[:install_moto()]`
+    async fn execute(&self, _ctx: &Ctx, code: &str, _runtime_task: &str) -> Result<String, MotoError> {
+        let name = match parse_invocation(code.trim()) {
+            Ok((_, invocation)) => invocation.name(),
+            Err(_) => code.trim().to_string(),
+        };
+        let fx = get_internal_function(&name).await.ok_or_else(|| MotoError::TaskNotFound {
+            name,
+            scope: Some("moto's internal functions".into()),
+        })?;
         showln!(green_bold, "⇣ ", gray_dim, "executing internal function");
-        let _ = fx().await;
-    } else {
-        showln!(red_bold, "⇣ ", gray_dim, "Function not found");
+        fx().await?;
+        Ok(String::new())
     }
-    Ok(())
 }
 
-async fn execute_simple_runtime(code: &str, runtime: &str) -> Result<(), String> {
-    let (command, arg) = match runtime {
-        "shell" | "sh" => ("bash", "-c"),
-        "powershell" | "ps" => ("pwsh", "-Command"),
-        _ => return Err(format!("Unsupported simple runtime: {}", runtime)),
-    };
+#[async_trait]
+impl Executor for Launcher {
+    async fn execute(&self, ctx: &Ctx, code: &str, _runtime_task: &str) -> Result<String, MotoError> {
+        execute_runtime(ctx, self, code).await
+    }
+}
 
-    execute_runtime(command, arg, code).await
-}
-
-async fn execute_complex_runtime(
-    code: &str,
-    runtime: &str,
-    runtime_task: &str,
-) -> Result<(), String> {
-    let runtime = get_runtime(runtime.to_string())
-        .await
-        .ok_or_else(|| format!("runtime {} not found", runtime))?;
-    let task = runtime
-        .get_task(runtime_task)
-        .ok_or_else(|| format!("task {} not found in runtime {}", runtime_task, runtime.name()))?;
-
-    let runtime = task.runtime();
-    let (command, arg) = match runtime.as_str() {
-        "shell" | "sh" => ("bash", "-c"),
-        "powershell" | "ps" => ("pwsh", "-Command"),
-        _ => return Err(format!("Unsupported runtime: {}", runtime)),
-    };
+#[async_trait]
+impl Executor for Resolved<Runtime> {
+    /// interpolates `code` into `[:block]` and runs the runtime task with its own tail.
+    /// the `let`s of the runtime are set in that scope too, e.g `[:version]` for `let version = "3.7.0";`,
+    /// unless the command line or the environment gives a value for them.
+    /// `[:block]` lives in a scope of its own so nested runtimes don't overwrite each other's,
+    /// the runtime task resolves names in the package the runtime is defined in
+    async fn execute(&self, ctx: &Ctx, code: &str, runtime_task: &str) -> Result<String, MotoError> {
+        let runtime = &self.cell;
+        let task = runtime.get_task(runtime_task).ok_or_else(|| MotoError::TaskNotFound {
+            name: runtime_task.to_string(),
+            scope: Some(format!("runtime {} defined at {}", runtime.name(), runtime.span)),
+        })?;
+
+        let scope = ctx.within(self.namespace.clone());
+        let executor = scope.get_executor(task.runtime()).await.map_err(|error| match error {
+            MotoError::RuntimeNotFound { name, .. } => MotoError::RuntimeNotFound {
+                name,
+                used_by: Some(format!("task {} defined at {}", task.name(), task.span)),
+            },
+            error => error,
+        })?;
 
-    let mut child = spawn_child_process(command, arg)?;
-    let mut stdin = child.stdin.take().expect("failed to get stdin");
+        // a value given on the command line or by the environment wins over the `let`
+        for assignment in runtime.assignments() {
+            if scope.given(&assignment.name()).await.is_some() {
+                continue;
+            }
+            let value = scope.evaluate_assignment(&assignment).await?;
+            scope.set_variable(assignment.name(), value).await;
+        }
+        let block_code = dope(ctx, code.to_string()).await?;
+        scope.set_variable("block", block_code.trim().into()).await;
 
-    execute_task_code(&task, code, &mut stdin).await?;
+        let task_code = task.get_code();
+        let task_code = task_code.trim_start_matches(['\r', '\n']);
+        executor.execute(&scope, task_code, "run").await
+    }
+}
 
-    stdin.flush().await.expect("failed to flush stdin");
-    drop(stdin);
+/// runs `code` with one of the built in shells.
+/// stdout is shown as it arrives and returned once the child is done
+async fn execute_runtime(ctx: &Ctx, launcher: &Launcher, code: &str) -> Result<String, MotoError> {
+    let lines = code.lines().map(|line| line.to_string()).collect::<Vec<String>>();
+    let Some(script) = launcher.script_path() else {
+        return execute_child(ctx, launcher, None, lines).await;
+    };
 
-    child.wait().await.expect("failed to wait on child");
+    let mut content = String::new();
+    for line in lines {
+        show_input_line(&line);
+        content.push_str(&dope(ctx, line).await?);
+        content.push('\n');
+    }
+    tokio::fs::write(&script, content).await?;
+    let result = execute_child(ctx, launcher, Some(&script), vec![]).await;
+    let _ = tokio::fs::remove_file(&script).await;
+    result
+}
 
-    Ok(())
+fn show_input_line(line: &str) {
+    let displayable = truncate_interpolatable_line(line.to_string(), 50);
+    if !displayable.is_empty() {
+        showln!(yellow_bold, "⇣ ", gray_dim, displayable);
+    }
 }
 
-async fn execute_runtime(command: &str, arg: &str, code: &str) -> Result<(), String> {
-    let mut child = spawn_child_process(command, arg)?;
-    let mut stdin = child.stdin.take().expect("failed to get stdin");
-    let stdout = child.stdout.take().expect("failed to get stdout");
-    let stderr = child.stderr.take().expect("failed to get stderr");
-    let lines = code.lines().map(|line| line.to_string()).collect::<Vec<String>>();
+/// spawns the shell and feeds it `lines` through stdin, interpolating each line right before it is sent
+async fn execute_child(ctx: &Ctx, launcher: &Launcher, script: Option<&std::path::Path>, lines: Vec<String>) -> Result<String, MotoError> {
+    let mut child = spawn_child_process(launcher, script)?;
+    let mut stdin = piped(child.stdin.take(), "stdin")?;
+    let stdout = piped(child.stdout.take(), "stdout")?;
+    let stderr = piped(child.stderr.take(), "stderr")?;
     let mut stdout_reader = BufReader::new(stdout).lines();
     let mut stderr_reader = BufReader::new(stderr).lines();
 
     let output_processor = tokio::spawn(async move {
-        while let Some(line) = stdout_reader.next_line().await.unwrap() {
+        let mut output = String::new();
+        while let Some(line) = stdout_reader.next_line().await? {
             show_output(&line);
+            output.push_str(&line);
+            output.push('\n');
         }
+        Ok::<_, MotoError>(output)
     });
 
     let error_processor = tokio::spawn(async move {
-        while let Some(line) = stderr_reader.next_line().await.unwrap() {
+        while let Some(line) = stderr_reader.next_line().await? {
             show_error(&line);
         }
+        Ok::<_, MotoError>(())
     });
 
+    let ctx = ctx.clone();
     let input_processor = tokio::spawn(async move {
         for line in lines {
-            let displayable = truncate_interpolatable_line(line.to_string(), 50);
-            if !displayable.is_empty() {
-                showln!(yellow_bold, "⇣ ", gray_dim, displayable);
-            }
+            show_input_line(&line);
+            let line = dope(&ctx, line).await?;
 
-            let line = dope(line.to_string()).await;
-
-            write_to_stdin(&mut stdin, &line).await.expect("failed to write to stdin");
+            // the child is gone once it stops reading, its exit status tells why
+            if write_to_stdin(&mut stdin, &line).await.is_err() {
+                break;
+            }
         }
-
+        Ok::<_, MotoError>(())
     });
 
-    tokio::try_join!(output_processor, error_processor, input_processor).map_err(|_| "failed to execute runtime".to_string())?;
+    // a line that could not be interpolated must not run half filled in, the child is stopped instead
+    let input = input_processor.await.map_err(joined)?;
+    if let Err(error) = input {
+        let _ = child.kill().await;
+        return Err(error);
+    }
+    let (output, errors) = tokio::try_join!(output_processor, error_processor).map_err(joined)?;
+    let output = output?;
+    errors?;
 
-    child.wait().await.expect("failed to wait on child");
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(MotoError::NonZeroExit { code: status.code() });
+    }
 
-    Ok(())
+    Ok(output)
 }
 
-fn spawn_child_process(command: &str, arg: &str) -> Result<tokio::process::Child, String> {
-    Command::new(command)
-        .arg("-NoProfile")
-        .arg(arg)
-        .arg("-")
+/// the pipes are always requested, a missing one means the platform could not set it up
+fn piped<T>(pipe: Option<T>, name: &str) -> Result<T, MotoError> {
+    pipe.ok_or_else(|| MotoError::Io(std::io::Error::other(format!("{} of the child process is not piped", name))))
+}
+
+/// a processor that panicked or was cancelled
+fn joined(error: tokio::task::JoinError) -> MotoError {
+    MotoError::Io(std::io::Error::other(error))
+}
+
+fn spawn_child_process(launcher: &Launcher, script: Option<&std::path::Path>) -> Result<tokio::process::Child, MotoError> {
+    launcher
+        .command(script)
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .spawn()
-        .map_err(|_| "failed to execute child".to_string())
-}
-
-async fn execute_task_code(
-    task: &Task,
-    code: &str,
-    stdin: &mut tokio::process::ChildStdin,
-) -> Result<(), String> {
-    let block_code = dope(code.to_string()).await;
-    let block_code = block_code.trim();
-    set_variable("block", block_code.into()).await;
-    for line in task.get_code().lines().skip_while(|line| line.is_empty()) {
-        let displayable = truncate_interpolatable_line(line.to_string(), 50);
-        if !displayable.is_empty() {
-            showln!(yellow_bold, "⇣ ", gray_dim, displayable);
-        }
-
-        let line = dope(line.to_string()).await;
-
-        write_to_stdin(stdin, &line).await?;
-    }
-
-    Ok(())
+        .map_err(|source| MotoError::Spawn { program: launcher.program.to_string(), source })
 }
 
-async fn write_to_stdin(stdin: &mut tokio::process::ChildStdin, line: &str) -> Result<(), String> {
-    stdin
-        .write_all(line.as_bytes())
-        .await
-        .map_err(|_| "failed to write to stdin".to_string())?;
-    stdin
-        .write_all(b"\n")
-        .await
-        .map_err(|_| "failed to write newline to stdin".to_string())
+async fn write_to_stdin(stdin: &mut tokio::process::ChildStdin, line: &str) -> Result<(), MotoError> {
+    stdin.write_all(line.as_bytes()).await?;
+    stdin.write_all(b"\n").await?;
+    Ok(())
 }
 
-pub async fn get_workspace() -> String {
-    let current_dir = std::env::current_dir().unwrap();
-    let workspace = current_dir.join("workspace");
+pub async fn get_workspace() -> Result<String, MotoError> {
+    let workspace = std::env::current_dir()?.join("workspace");
     if !workspace.exists() {
-        tokio::fs::create_dir_all(&workspace)
-            .await
-            .expect("failed to create workspace");
+        tokio::fs::create_dir_all(&workspace).await?;
     }
-    workspace.to_str().unwrap().to_string()
+    Ok(workspace.to_string_lossy().to_string())
 }
 
 impl Runtime {
@@ -191,9 +290,22 @@ impl Runtime {
             })
             .next()
     }
+
+    /// the `let`s of the runtime, its tasks see them as variables
+    pub fn assignments(&self) -> Vec<Assignment> {
+        self.children
+            .iter()
+            .filter_map(|cell| match cell {
+                Cell::Assignment(assignment) => Some(assignment.clone()),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
-pub async fn dope(code: String) -> String {
+/// fills in the `[:...]` of a line: variables, variables with defaults and task calls.
+/// a variable without a value is an error, `rm -rf [:dir]/` must not run as `rm -rf /`
+pub async fn dope(ctx: &Ctx, code: String) -> Result<String, MotoError> {
     let mut result = String::new();
     let mut start = 0;
 
@@ -201,25 +313,34 @@ pub async fn dope(code: String) -> String {
         result.push_str(&code[start..start + new_start]);
 
         let segment = &code[start + new_start..start + end];
-        let value = match segment.find('(') {
-            Some(open) => {
-                let close = segment[open..].find(')').unwrap() + open;
-                let (name, args) = segment.split_at(open);
-                let args = &args[1..close];
-                let args = args.split(',').map(|arg| arg.trim()).collect::<Vec<&str>>();
-                get_function_value(name, args).await
-            }
-            None => match segment.find('=') {
-                Some(equal) => {
-                    let (name, default) = segment.split_at(equal);
-                    let default = &default[1..].trim_end_matches(']');
-                    let name = name.trim_start_matches("[:").trim_end_matches("]");
-                    get_variable_or_default(name, *default).await.to_string()
-                }
-                None => {
-                    let name = segment.trim_start_matches("[:").trim_end_matches("]");
-                    get_variable_or_default(name, "").await.to_string()
-                }
+        // `[:who=(mom | dad)]` and `[:port::number=8080]` are variables with a constraint, the value is checked
+        let value = match parse_variable_reference(segment) {
+            Ok((_, variable)) if variable.constraint.is_some() => match ctx.resolve_variable(&variable.name()).await? {
+                Some(value) => variable.coerce(&value)?.to_string(),
+                None if variable.has_default() => variable.coerce(&ctx.evaluate(&variable.value).await?)?.to_string(),
+                None => return Err(MotoError::MissingVariables { names: vec![variable.name()] }),
+            },
+            // a call has its `(` before any `=`
+            _ => match segment.find(['(', '=']).filter(|&at| segment[at..].starts_with('(')) {
+                Some(_) => get_function_value(ctx, segment).await?,
+                None => match segment.find('=') {
+                    Some(equal) => {
+                        let (name, default) = segment.split_at(equal);
+                        let default = &default[1..].trim_end_matches(']');
+                        let name = name.trim_start_matches("[:").trim_end_matches("]");
+                        match ctx.resolve_variable(name).await? {
+                            Some(value) => value.to_string(),
+                            None => default.to_string(),
+                        }
+                    }
+                    None => {
+                        let name = segment.trim_start_matches("[:").trim_end_matches("]");
+                        match ctx.resolve_variable(name).await? {
+                            Some(value) => value.to_string(),
+                            None => return Err(MotoError::MissingVariables { names: vec![name.trim().to_string()] }),
+                        }
+                    }
+                },
             },
         };
 
@@ -228,7 +349,7 @@ pub async fn dope(code: String) -> String {
     }
 
     result.push_str(&code[start..]);
-    result
+    Ok(result)
 }
 
 fn find_interpolatable(code: &str) -> Option<(usize, usize)> {
@@ -237,15 +358,14 @@ fn find_interpolatable(code: &str) -> Option<(usize, usize)> {
     Some((start, start + end + 1))
 }
 
-async fn get_function_value(name: &str, args: Vec<&str>) -> String {
-    let name = name.trim_start_matches("[:").trim_end_matches("]");
-    if let Some(value) = get_function(name).await {
-        let runtime = value.runtime();
-        let runtime_task = value.name();
-        let code = value.get_code();
-        // function not implemented
-        "".into()
-    } else {
-        "".into()
+/// calls the task in an interpolation like `[:greet_from_dart(compile)]` and returns its trimmed output
+async fn get_function_value(ctx: &Ctx, segment: &str) -> Result<String, MotoError> {
+    let failed = |reason: String| MotoError::Interpolation { segment: segment.to_string(), reason };
+    let (_, invocation) = parse_invocation(segment)
+        .map_err(|e| failed(ParseError::from_nom(segment, e).message()))?;
+
+    match call(ctx, &invocation).await {
+        Ok(output) => Ok(output.trim().to_string()),
+        Err(e) => Err(failed(e.to_string())),
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,271 @@
+use super::*;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq)]
+///where the value of a variable comes from. when several give one the first of them wins, in this order
+pub enum Source {
+    ///`moto build [:root=/srv]`
+    CommandLine,
+    ///an environment variable like `MOTO_ROOT=/srv`, by its name
+    Environment(String),
+    ///a line of the `.env` file next to the scripts, e.g `.env:3`
+    DotEnv(String),
+    ///`let root = "/srv";`, where it is defined
+    Let(String),
+    ///`[:root="/srv"]` in a task, where the task is defined
+    Default(String),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::CommandLine => write!(f, "command line"),
+            Source::Environment(name) => write!(f, "environment variable {}", name),
+            Source::DotEnv(location) => write!(f, "{}", location),
+            Source::Let(location) => write!(f, "let at {}", location),
+            Source::Default(location) => write!(f, "default at {}", location),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+///the value of a variable and where it comes from
+pub struct Setting {
+    pub value: Atom,
+    pub source: Source,
+}
+
+#[derive(Debug, Clone, Default)]
+///the variables given from outside the scripts: `MOTO_*` environment variables and the `.env` file
+pub struct Environment {
+    ///by the name of the variable they give, e.g `root` for `MOTO_ROOT`. environment variables win over the `.env` file
+    pub variables: HashMap<String, Setting>,
+    ///the entries of the `.env` file as they are written, `[:env.NAME]` falls back to them
+    pub dotenv: HashMap<String, Setting>,
+}
+
+impl Environment {
+    /// the environment made of the entries of a `.env` file and environment variables like `("MOTO_ROOT", "/srv")`.
+    /// only the entries starting with `MOTO_` give variables, in the `.env` file as in the environment
+    pub fn new(dotenv: HashMap<String, Setting>, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut variables = HashMap::new();
+        for (key, setting) in &dotenv {
+            if key.starts_with("MOTO_") {
+                variables.insert(variable_name(key), setting.clone());
+            }
+        }
+        for (key, value) in vars {
+            if key.starts_with("MOTO_") {
+                let setting = Setting { value: value.into(), source: Source::Environment(key.clone()) };
+                variables.insert(variable_name(&key), setting);
+            }
+        }
+        Environment { variables, dotenv }
+    }
+
+    /// the `.env` file of `dir`, if there is one, and the environment of this process
+    pub fn load(dir: &Path) -> Result<Self, MotoError> {
+        let path = dir.join(".env");
+        let dotenv = match path.is_file() {
+            true => parse_dotenv(".env", &std::fs::read_to_string(&path)?),
+            false => HashMap::new(),
+        };
+        let vars = std::env::vars_os().filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)));
+        Ok(Environment::new(dotenv, vars))
+    }
+
+    /// the value of `[:env.NAME]`: the environment variable `NAME` or else the `.env` entry of that name
+    pub fn get(&self, name: &str) -> Option<Atom> {
+        match std::env::var(name) {
+            Ok(value) => Some(Atom::String(value)),
+            Err(_) => self.dotenv.get(name).map(|setting| setting.value.clone()),
+        }
+    }
+}
+
+/// the name of the variable an environment variable gives, `root` for `MOTO_ROOT`
+fn variable_name(key: &str) -> String {
+    key.strip_prefix("MOTO_").unwrap_or(key).to_lowercase()
+}
+
+/// the entries of a `.env` file by their name. a line is `NAME=value`, optionally starting with `export`,
+/// the value can be quoted. blank lines and lines starting with `#` are skipped, so is `# ...` after an unquoted value
+pub fn parse_dotenv(file: &str, source: &str) -> HashMap<String, Setting> {
+    let mut entries = HashMap::new();
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let quoted = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].find(quote).map(|end| &value[1..end + 1]),
+            _ => None,
+        };
+        let value = quoted.unwrap_or_else(|| value.split(" #").next().unwrap_or_default().trim_end());
+        let setting = Setting {
+            value: value.to_string().into(),
+            source: Source::DotEnv(format!("{}:{}", file, i + 1)),
+        };
+        entries.insert(key.trim().to_string(), setting);
+    }
+    entries
+}
+
+impl Ctx {
+    /// reads the `.env` file of `dir`, the directory of the scripts, and the `MOTO_*` environment variables
+    pub async fn load_environment(&self, dir: &Path) -> Result<(), MotoError> {
+        *self.environment.lock().await = Environment::load(dir)?;
+        Ok(())
+    }
+
+    /// the value given to `[:name]` from outside the scripts: on the command line, or else by the environment.
+    /// these win over the `let`s and defaults of the scripts
+    pub async fn given(&self, name: &str) -> Option<Setting> {
+        let name = name.trim().to_lowercase();
+        let mut root = self;
+        while let Some(parent) = root.parent.as_deref() {
+            root = parent;
+        }
+        if let Some(value) = root.variables.lock().await.get(&name) {
+            return Some(Setting { value: value.clone(), source: Source::CommandLine });
+        }
+        self.environment.lock().await.variables.get(&name).cloned()
+    }
+
+    /// every variable the scripts use or that is given from outside them, with the value it has and where that
+    /// comes from. the `let`s of packages are named like `rust:version`. a `let` that calls a task is not run,
+    /// its expression is shown instead
+    pub async fn settings(&self) -> Vec<(String, Setting)> {
+        let mut settings = Vec::<(String, Setting)>::new();
+        let mut add = |name: String, setting: Setting| {
+            if !settings.iter().any(|(known, _)| known.eq_ignore_ascii_case(&name)) {
+                settings.push((name, setting));
+            }
+        };
+
+        let mut found = vec![];
+        script_settings(&self.get_children().await, &mut vec![], &mut found);
+        for (namespace, name, setting) in found {
+            let member = split_qualified(&name).1;
+            let display = match namespace.get(1..) {
+                Some(namespace) if !namespace.is_empty() => format!("{}:{}", namespace.join(":"), member),
+                _ => member.clone(),
+            };
+            if let Some(given) = self.given(&member).await {
+                add(display, given);
+                continue;
+            }
+            let value = match (&setting.source, &setting.value) {
+                (Source::Let(_), value) if !has_calls(value) => {
+                    // without executors nothing can run, not even a task called by a `let` this one uses
+                    let scope = Ctx { executors: Arc::new(Mutex::new(HashMap::new())), ..self.within(namespace) };
+                    let assignment = Assignment::new(member.as_str(), value.clone());
+                    scope.evaluate_assignment(&assignment).await.unwrap_or(value.clone())
+                }
+                (_, value) => value.clone(),
+            };
+            add(display, Setting { value, ..setting });
+        }
+
+        let mut root = self;
+        while let Some(parent) = root.parent.as_deref() {
+            root = parent;
+        }
+        let command_line = root.variables.lock().await.clone();
+        for (name, value) in command_line {
+            add(name, Setting { value, source: Source::CommandLine });
+        }
+        let mut environment = self.environment.lock().await.variables.clone().into_iter().collect::<Vec<_>>();
+        environment.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, setting) in environment {
+            add(name, setting);
+        }
+        settings
+    }
+}
+
+/// the `let`s of `cells` and the `[:name=default]`s of their tasks at any depth, with the package they are in
+fn script_settings(cells: &[Cell], namespace: &mut Vec<String>, into: &mut Vec<(Vec<String>, String, Setting)>) {
+    for cell in cells {
+        match cell {
+            Cell::Assignment(assignment) => {
+                let setting = Setting { value: assignment.value.clone(), source: Source::Let(assignment.span.to_string()) };
+                into.push((namespace.clone(), assignment.name(), setting));
+            }
+            Cell::Task(task) => {
+                let source = Source::Default(task.span.to_string());
+                let parameters = task.signature.parameters().into_iter().filter(Variable::has_default);
+                for variable in parameters.chain(body_defaults(&task.body)) {
+                    let setting = Setting { value: variable.value.clone(), source: source.clone() };
+                    into.push((namespace.clone(), variable.name(), setting));
+                }
+            }
+            Cell::Runtime(runtime) => script_settings(&runtime.children, namespace, into),
+            Cell::Package(package) => {
+                namespace.push(package.name());
+                script_settings(&package.children, namespace, into);
+                namespace.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// the `[:name=default]`s of a body, e.g `name` with `x` for `echo hello [:name=x]`.
+/// alternatives like `[:name=(mom | dad)]` are no default
+fn body_defaults(body: &str) -> Vec<Variable> {
+    parse_body_variables(body)
+        .into_iter()
+        .filter(|variable| variable.has_default() && !variable.name().starts_with("env."))
+        .collect()
+}
+
+/// whether computing `atom` would run a task
+pub(crate) fn has_calls(atom: &Atom) -> bool {
+    match atom {
+        Atom::Function(_) => true,
+        Atom::Array(array) => array.values.iter().any(has_calls),
+        Atom::Object(object) => object.values.iter().any(|(_, value)| has_calls(value)),
+        Atom::Variable(variable) => has_calls(&variable.value),
+        Atom::UnaryOperation(operation) => has_calls(&operation.operand),
+        Atom::BinaryOperation(operation) => has_calls(&operation.left) || has_calls(&operation.right),
+        _ => false,
+    }
+}
+
+#[tokio::test]
+async fn test_variable_sources() {
+    let input = "let root = [:root=\"/srv\"];\nlet region = \"eu\";\nlet user = \"me\";\ntask deploy { echo [:target=prod] [:user] }:shell\n";
+    let ctx = Ctx::from_source(input).await;
+    let dotenv = parse_dotenv(".env", "# deploy\nMOTO_REGION=us\nexport MOTO_TEST_TOKEN=\"a b\" # secret\nUSER=dotenv\nTEST_PLAIN=x\n");
+    assert_eq!(dotenv["MOTO_TEST_TOKEN"].value, Atom::from("a b"));
+    *ctx.environment.lock().await = Environment::new(dotenv, vec![("MOTO_USER".to_string(), "env".to_string())]);
+    ctx.set_variable("root", Atom::from("/opt")).await;
+
+    assert_eq!(ctx.resolve_variable("root").await.unwrap(), Some(Atom::from("/opt")));
+    assert_eq!(ctx.resolve_variable("user").await.unwrap(), Some(Atom::from("env")));
+    assert_eq!(ctx.resolve_variable("region").await.unwrap(), Some(Atom::from("us")));
+    assert_eq!(ctx.resolve_variable("env.MOTO_TEST_TOKEN").await.unwrap(), Some(Atom::from("a b")));
+    // entries without `MOTO_` are only there for `[:env.NAME]`
+    assert_eq!(ctx.resolve_variable("test_plain").await.unwrap(), None);
+    assert_eq!(ctx.resolve_variable("env.TEST_PLAIN").await.unwrap(), Some(Atom::from("x")));
+    let sources = ctx.settings().await.into_iter().map(|(name, setting)| format!("{} {}", name, setting.source)).collect::<Vec<_>>();
+    assert_eq!(
+        sources,
+        vec![
+            "root command line",
+            "region .env:2",
+            "user environment variable MOTO_USER",
+            "target default at im.moto:4",
+            "test_token .env:3",
+        ]
+    );
+}